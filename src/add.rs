@@ -1,42 +1,134 @@
-use reqwest::Url;
+use std::error::Error;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, FixedOffset, Local};
 
 use commands::RTAdd;
-use config::RTConfig;
 
-use feed_util::{self, Feeds, Feed};
-use alias::Aliases;
-use alias_util;
+use alias::AliasRef;
+use feed_parse;
+use feed_util::{Feed, Feeds};
+use history_util::HistoryEntry;
+use item;
+use redact::RedactedUrl;
+use resolve;
+use store::Store;
 
-pub fn add_feed(cmd: RTAdd, config: &RTConfig) {
-    let result = Url::parse(&cmd.url_or_alias);
-    let url: Url;
-    match result {
-        Ok(u) => url = u,
-        Err(_) => {
-            trace!("url_or_alias is not a valid url, checking if it is an alias...");
-            let aliases_result = alias_util::read_alias_db(config.data_dir.as_path());
-            let aliases: Aliases;
-            match aliases_result {
-                Ok(a) => aliases = a,
-                Err(alias_err) => {
-                    error!("Could not read alias db: {}", alias_err);
-                    error!("Not adding feed because url_or_alias could not be understood.");
-                    return;
-                }
-            }
-            match aliases.get(&cmd.url_or_alias) {
-                Some(alias) => url = alias.url.clone(),
-                None => {
-                    error!("Not adding feed because url_or_alias {} was not a url or a valid alias.", &cmd.url_or_alias);
-                    return;
-                }
-            }
+/// Where a new feed starts, for `add --since`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Since {
+    Now,
+    Time(DateTime<FixedOffset>),
+    Ago(Duration),
+}
+
+impl FromStr for Since {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Since, String> {
+        if s == "now" {
+            return Ok(Since::Now);
+        }
+        if let Some(time) = item::parse_rfc3339(s) {
+            return Ok(Since::Time(time));
+        }
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let amount: i64 = match s[..split].parse() {
+            Ok(amount) => amount,
+            Err(_) => return Err(format!("Invalid time {}, expected now, a RFC 3339 timestamp or a \
+                                          duration like 3d", s)),
+        };
+
+        let minutes_per_unit = match &s[split..] {
+            "m" => 1,
+            "h" => 60,
+            "d" => 24 * 60,
+            "w" => 7 * 24 * 60,
+            unit => return Err(format!("Unknown unit {} in {}, expected m, h, d or w", unit, s)),
+        };
+
+        // Duration panics above i64::MAX milliseconds, and subtracting it from now panics if that
+        // is before the earliest date chrono supports
+        let duration = amount.checked_mul(minutes_per_unit)
+            .filter(|&minutes| minutes <= i64::max_value() / 60_000)
+            .map(Duration::minutes);
+        match duration {
+            Some(duration) if Local::now().checked_sub_signed(duration).is_some() => {
+                Ok(Since::Ago(duration))
+            },
+            _ => Err(format!("Invalid time {}, that is too long ago", s)),
+        }
+    }
+}
+
+impl Since {
+    pub fn to_time(self) -> DateTime<FixedOffset> {
+        let now = Local::now();
+        let now = now.with_timezone(now.offset());
+
+        match self {
+            Since::Now => now,
+            Since::Time(time) => time,
+            Since::Ago(duration) => now - duration,
         }
     }
+}
+
+/// The `last_update` a new feed starts with. `--initial-sync` and `--backfill` fetch the feed to
+/// find it: the newest item's date, or the date of the newest match that is not backfilled, so
+/// that only the newer ones are added on the next update. Items without a date are always new, so
+/// with `--initial-sync` the ones already in the feed are also returned as history entries, which
+/// makes the next update skip them.
+fn initial_last_update(cmd: &RTAdd, feed: &Feed)
+    -> Result<(Option<DateTime<FixedOffset>>, Vec<HistoryEntry>), Box<Error>> {
+    if let Some(since) = cmd.since {
+        return Ok((Some(since.to_time()), Vec::new()));
+    }
+    if !cmd.initial_sync && cmd.backfill.is_none() {
+        return Ok((None, Vec::new()));
+    }
 
-    let result = feed_util::read_feed_db(config.data_dir.as_path());
+    let items = feed_parse::fetch_feed(&feed.url)?;
+    let mut dates: Vec<DateTime<FixedOffset>> = items.iter()
+        .filter(|item| cmd.initial_sync || feed.matches(item))
+        .filter_map(|item| item.date())
+        .collect();
+    dates.sort_by(|a, b| b.cmp(a));
 
-    let mut feeds: Feeds;
+    if cmd.initial_sync {
+        let now = Since::Now.to_time();
+        let skipped = items.iter()
+            .filter(|item| item.date().is_none() && (item.id.is_some() || item.info_hash.is_some()))
+            .map(|item| HistoryEntry {
+                feed_url: feed.url.to_string(),
+                title: item.title.clone(),
+                guid: item.id.clone(),
+                info_hash: item.info_hash.clone(),
+                dispatched: now,
+            })
+            .collect();
+
+        // without any dates in the feed, nothing already in it counts as new after now
+        return Ok((dates.first().cloned().or(Some(now)), skipped));
+    }
+
+    // if there are fewer matches than the backfill, all of them are added
+    Ok((cmd.backfill.and_then(|n| dates.get(n).cloned()), Vec::new()))
+}
+
+pub fn add_feed(cmd: RTAdd, store: &mut Store) {
+    let url = match resolve::resolve_url(store, &cmd.url_or_alias) {
+        Ok(u) => u,
+        Err(err) => {
+            error!("Not adding feed because url_or_alias could not be understood: {}", err);
+            return;
+        }
+    };
+
+    let result = store.list_feeds();
+
+    let feeds: Feeds;
     match result {
         Ok(read_feeds) => feeds = read_feeds,
         Err(err) => {
@@ -47,26 +139,102 @@ pub fn add_feed(cmd: RTAdd, config: &RTConfig) {
 
     trace!("Read feeds db with {} entries.", feeds.len());
 
-    // TODO add option to set time to None or to current time
-    let new_feed = Feed {url: url, last_update: None, filters: cmd.filters};
+    let mut new_feed = Feed::new(url, cmd.filters.clone(), &feeds);
+
+    if cmd.link_alias {
+        // the parameters were already checked by resolve_url
+        let alias_ref = AliasRef::parse(&cmd.url_or_alias).ok();
+        let alias = match alias_ref {
+            Some(ref r) => store.get_alias(&r.name),
+            None => Ok(None),
+        };
+
+        match alias {
+            Ok(Some(_)) => new_feed.alias = alias_ref.map(|r| r.to_string()),
+            Ok(None) => {
+                error!("{} is not an alias, so there is nothing to link. Not adding feed.",
+                       cmd.url_or_alias);
+                return;
+            },
+            Err(err) => {
+                error!("Could not read alias db: {}. Not adding feed.", err);
+                return;
+            }
+        }
+    }
 
-    if feeds.contains(&new_feed) {
-        error!("Feed with url {} and filters \"{}\" already exists in db. Not adding feed.",
-               new_feed.url.as_str(),
-               new_feed.filters.join(",")
-        );
+    if let Some(existing) = feeds.get(&new_feed) {
+        error!("Feed {} already exists in db. Not adding feed.", existing);
         return;
     }
-    else {
-        info!("Adding new feed {} with filters \"{}\"", new_feed.url.as_str(),
-              new_feed.filters.join(",")
-        );
+
+    let skipped;
+    match initial_last_update(&cmd, &new_feed) {
+        Ok((last_update, entries)) => {
+            new_feed.last_update = last_update;
+            skipped = entries;
+        },
+        Err(err) => {
+            error!("Could not fetch feed {}: {}. Not adding feed.", RedactedUrl(&new_feed.url),
+                   err);
+            return;
+        }
     }
 
-    feeds.insert(new_feed);
+    if let Some(last_update) = new_feed.last_update {
+        info!("Only items published after {} will be added.", last_update.to_rfc3339());
+    }
 
-    match feed_util::write_feed_db(config.data_dir.as_path(), feeds) {
-        Ok(()) => info!("Sucessfully added feed to db."),
+    let description = new_feed.to_string();
+    let mut inserted = false;
+    let result = store.transaction(&mut |tx| {
+        inserted = tx.insert_feed(new_feed.clone())?;
+        if inserted && !skipped.is_empty() {
+            tx.record_history(&skipped)?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) if inserted => info!("Sucessfully added feed {} to db.", description),
+        Ok(()) => error!("Feed {} already exists in db. Not adding feed.", description),
         Err(err) => error!("Could not add feed to db: {}", err),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_since() {
+        assert_eq!("now".parse(), Ok(Since::Now));
+        assert_eq!("2018-10-16T08:30:00Z".parse(),
+                   Ok(Since::Time(DateTime::parse_from_rfc3339("2018-10-16T08:30:00Z").unwrap())));
+        assert_eq!("90m".parse(), Ok(Since::Ago(Duration::minutes(90))));
+        assert_eq!("12h".parse(), Ok(Since::Ago(Duration::hours(12))));
+        assert_eq!("3d".parse(), Ok(Since::Ago(Duration::days(3))));
+        assert_eq!("2w".parse(), Ok(Since::Ago(Duration::weeks(2))));
+        assert_eq!("0d".parse(), Ok(Since::Ago(Duration::zero())));
+    }
+
+    #[test]
+    fn rejects_invalid_since() {
+        assert!("yesterday".parse::<Since>().is_err());
+        assert!("3y".parse::<Since>().is_err());
+        assert!("-3d".parse::<Since>().is_err());
+        assert!("d".parse::<Since>().is_err());
+    }
+
+    #[test]
+    fn rejects_since_too_long_ago() {
+        for since in &["999999999999d", "999999999999w", "9223372036854775807m",
+                       "99999999999999999999d"] {
+            let result = since.parse::<Since>();
+            assert!(result.is_err(), "{}", since);
+        }
+
+        // far back, but still a date
+        assert!("5200w".parse::<Since>().unwrap().to_time() < Since::Now.to_time());
+    }
+}
@@ -1,15 +1,20 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
 
 use reqwest::Url;
 
-use commands::RTAlias;
-use config::RTConfig;
+use commands::{RTAlias, RTAliasCommand, RTAliasRename, RTAliasRm, RTAliasSet};
 
-use alias_util;
+use feed_util::{Feed, Feeds};
+use list::{self, SortKey};
+use store::Store;
 
 pub type Aliases = HashMap<String, Alias>;
 
+/// The url may be a template containing `{name}` placeholders, which are filled in from the
+/// parameters of an `AliasRef` with `fill`.
 #[derive(Debug, Clone)]
 pub struct Alias {
     pub name: String,
@@ -23,6 +28,144 @@ impl Alias {
             url: self.url.to_string(),
         }
     }
+
+    // Url::parse percent-encodes braces in the path but not in the query, so undo that to find
+    // the placeholders.
+    fn template(&self) -> String {
+        self.url.as_str()
+            .replace("%7B", "{").replace("%7b", "{")
+            .replace("%7D", "}").replace("%7d", "}")
+    }
+
+    /// Names of the placeholders in the url, in order of appearance and without duplicates.
+    pub fn placeholders(&self) -> Vec<String> {
+        let template = self.template();
+        let mut names = Vec::new();
+
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            rest = &rest[start + 1..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+
+            let name = &rest[..end];
+            if is_placeholder_name(name) && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+
+        names
+    }
+
+    /// The url with every placeholder replaced by the percent-encoded value of the parameter with
+    /// the same name. Every placeholder must be given a value, and every parameter must have a
+    /// placeholder.
+    pub fn fill(&self, params: &[(String, String)]) -> Result<Url, Box<Error>> {
+        let placeholders = self.placeholders();
+
+        let missing: Vec<&str> = placeholders.iter()
+            .filter(|p| !params.iter().any(|&(ref k, _)| k == *p))
+            .map(|p| p.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(Box::new(io::Error::new(ErrorKind::InvalidInput,
+                format!("Alias {} needs a value for {}, e.g. {}:{}", self.name, missing.join(", "),
+                        self.name, missing.iter().map(|m| format!("{}=...", m))
+                                          .collect::<Vec<_>>().join(",")))));
+        }
+
+        if let Some(&(ref key, _)) = params.iter().find(|&&(ref k, _)| !placeholders.contains(k)) {
+            return Err(Box::new(io::Error::new(ErrorKind::InvalidInput,
+                format!("Alias {} has no placeholder {{{}}}", self.name, key))));
+        }
+
+        // replace the placeholders in the url itself rather than in the template, so that an
+        // encoded brace that isn't part of a placeholder stays encoded, and in a single pass, so
+        // that a value is never mistaken for a placeholder
+        let url = self.url.as_str();
+        let mut filled = String::new();
+        let mut i = 0;
+        'outer: while i < url.len() {
+            for &(ref key, ref value) in params {
+                for form in &[format!("{{{}}}", key), format!("%7B{}%7D", key), format!("%7b{}%7d", key)] {
+                    if url[i..].starts_with(form.as_str()) {
+                        filled.push_str(&percent_encode(value));
+                        i += form.len();
+                        continue 'outer;
+                    }
+                }
+            }
+
+            let c = url[i..].chars().next().unwrap();
+            filled.push(c);
+            i += c.len_utf8();
+        }
+
+        Ok(Url::parse(&filled)?)
+    }
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Encode everything except the unreserved characters, so values are safe anywhere in the url.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    encoded
+}
+
+/// A reference to an alias as given on the command line: its name, optionally followed by `:` and
+/// comma separated `key=value` parameters for its placeholders, e.g. `tracker:cat=41,q=foo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasRef {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl AliasRef {
+    pub fn parse(arg: &str) -> Result<AliasRef, Box<Error>> {
+        let (name, params) = match arg.find(':') {
+            Some(i) => (&arg[..i], &arg[i + 1..]),
+            None => return Ok(AliasRef { name: arg.to_string(), params: Vec::new() }),
+        };
+
+        let mut parsed = Vec::new();
+        for param in params.split(',').filter(|p| !p.is_empty()) {
+            match param.find('=') {
+                Some(i) if is_placeholder_name(&param[..i]) => {
+                    parsed.push((param[..i].to_string(), param[i + 1..].to_string()));
+                },
+                _ => {
+                    return Err(Box::new(io::Error::new(ErrorKind::InvalidInput,
+                        format!("Invalid alias parameter \"{}\", expected key=value", param))));
+                }
+            }
+        }
+
+        Ok(AliasRef { name: name.to_string(), params: parsed })
+    }
+}
+
+impl fmt::Display for AliasRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.params.is_empty() {
+            let params: Vec<String> = self.params.iter().map(|&(ref k, ref v)| format!("{}={}", k, v))
+                .collect();
+            write!(f, ":{}", params.join(","))?;
+        }
+        Ok(())
+    }
 }
 
 // Only pub so we can use it both here and in alias_util. Ideally alias_util would just be a
@@ -43,7 +186,34 @@ impl AliasRecord {
         })
     }
 }
-pub fn add_alias(cmd: RTAlias, config: &RTConfig) {
+
+pub fn run_alias(cmd: RTAlias, store: &mut Store) {
+    match cmd.cmd {
+        RTAliasCommand::Set(set) => set_alias(set, store),
+        RTAliasCommand::Rm(rm) => remove_alias(rm, store),
+        RTAliasCommand::Rename(rename) => rename_alias(rename, store),
+        RTAliasCommand::List(list_cmd) => {
+            if let Err(err) = list::list_aliases(store, list_cmd.format, SortKey::Id, false) {
+                error!("Could not list aliases: {}", err);
+            }
+        },
+    }
+}
+
+/// Feeds that follow the alias `name`, along with their reference to it.
+fn linked_feeds(feeds: &Feeds, name: &str) -> Vec<(Feed, AliasRef)> {
+    feeds.iter()
+        .filter_map(|f| {
+            let alias_ref = f.alias.as_ref().and_then(|a| AliasRef::parse(a).ok());
+            match alias_ref {
+                Some(r) => if r.name == name { Some((f.clone(), r)) } else { None },
+                None => None,
+            }
+        })
+        .collect()
+}
+
+fn set_alias(cmd: RTAliasSet, store: &mut Store) {
     let result = Url::parse(&cmd.url);
     let url: Url;
     match result {
@@ -54,30 +224,300 @@ pub fn add_alias(cmd: RTAlias, config: &RTConfig) {
         }
     }
 
-    let result = alias_util::read_alias_db(config.data_dir.as_path());
+    let new_alias = Alias {name: cmd.alias.clone(), url: url.clone()};
+
+    let mut old_alias = None;
+    let mut changed_feeds = 0;
+    let result = store.transaction(&mut |tx| {
+        old_alias = tx.insert_alias(new_alias.clone())?;
+        let old_url = old_alias.as_ref().map(|a| a.url.clone());
+
+        // feeds following the alias always get the new url, others only with --cascade
+        let feeds = tx.list_feeds()?;
+        let linked = linked_feeds(&feeds, &cmd.alias);
+        for feed in &feeds {
+            let new_url = match linked.iter().find(|&&(ref f, _)| f.id == feed.id) {
+                Some(&(_, ref alias_ref)) => match new_alias.fill(&alias_ref.params) {
+                    Ok(u) => u,
+                    Err(err) => {
+                        warn!("Not changing the url of feed {}: {}", feed, err);
+                        continue;
+                    }
+                },
+                None if cmd.cascade && old_url.as_ref() == Some(&feed.url)
+                    && new_alias.placeholders().is_empty() => url.clone(),
+                None => continue,
+            };
+
+            if feed.url == new_url {
+                continue;
+            }
+
+            let mut updated = feed.clone();
+            updated.url = new_url;
+            if feeds.contains(&updated) {
+                warn!("Not changing the url of feed {}, there already is a feed with the new url \
+                       and the same filters.", feed);
+                continue;
+            }
+
+            tx.update_feed(feed, updated)?;
+            changed_feeds += 1;
+        }
+
+        Ok(())
+    });
 
-    let mut aliases: Aliases;
     match result {
-        Ok(a) => aliases = a,
-        Err(err) => {
-            error!("Could not read alias db: {}. Not updating aliases.", err);
-            return;
-        },
+        Ok(()) if old_alias.is_some() => info!("Sucessfully updated alias {} and {} feeds.",
+                                               cmd.alias, changed_feeds),
+        Ok(()) => info!("Sucessfully added new alias {}.", cmd.alias),
+        Err(err) => error!("Could not update alias db: {}", err),
+    }
+}
+
+fn remove_alias(cmd: RTAliasRm, store: &mut Store) {
+    let result = store.transaction(&mut |tx| {
+        let linked = linked_feeds(&tx.list_feeds()?, &cmd.alias);
+        if !linked.is_empty() && !cmd.unlink {
+            let names: Vec<String> = linked.iter().map(|&(ref f, _)| f.to_string()).collect();
+            return Err(Box::new(io::Error::new(ErrorKind::Other,
+                format!("Feeds follow this alias, use --unlink to keep them with the current url: {}",
+                        names.join("; ")))));
+        }
+
+        for (feed, _) in linked {
+            let mut updated = feed.clone();
+            updated.alias = None;
+            tx.update_feed(&feed, updated)?;
+        }
+
+        match tx.remove_alias(&cmd.alias)? {
+            Some(_) => Ok(()),
+            None => Err(Box::new(io::Error::new(ErrorKind::NotFound,
+                                                format!("No alias named {}", cmd.alias)))),
+        }
+    });
+
+    match result {
+        Ok(()) => info!("Sucessfully removed alias {}.", cmd.alias),
+        Err(err) => error!("Could not remove alias: {}", err),
+    }
+}
+
+fn rename_alias(cmd: RTAliasRename, store: &mut Store) {
+    let result = store.transaction(&mut |tx| {
+        if tx.get_alias(&cmd.new)?.is_some() {
+            return Err(Box::new(io::Error::new(ErrorKind::AlreadyExists,
+                                               format!("Alias {} already exists", cmd.new))));
+        }
+
+        let alias = match tx.remove_alias(&cmd.old)? {
+            Some(a) => a,
+            None => return Err(Box::new(io::Error::new(ErrorKind::NotFound,
+                                                       format!("No alias named {}", cmd.old)))),
+        };
+        tx.insert_alias(Alias { name: cmd.new.clone(), url: alias.url })?;
+
+        for (feed, mut alias_ref) in linked_feeds(&tx.list_feeds()?, &cmd.old) {
+            alias_ref.name = cmd.new.clone();
+
+            let mut updated = feed.clone();
+            updated.alias = Some(alias_ref.to_string());
+            tx.update_feed(&feed, updated)?;
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => info!("Sucessfully renamed alias {} to {}.", cmd.old, cmd.new),
+        Err(err) => error!("Could not rename alias: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use memory_store::MemoryStore;
+
+    fn tracker(url: &str) -> Alias {
+        Alias { name: "tracker".to_string(), url: Url::parse(url).unwrap() }
+    }
+
+    fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        params.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
     }
 
-    if aliases.contains_key(&cmd.alias) {
-        info!("Updating alias {}", cmd.alias);
+    #[test]
+    fn finds_placeholders_in_path_and_query() {
+        let alias = tracker("https://t.example/{cat}/rss?q={q}&c={cat}&x={not a name}");
+        assert_eq!(alias.placeholders(), vec!["cat", "q"]);
     }
-    else {
-        info!("Adding new alias {}", cmd.alias);
+
+    #[test]
+    fn fills_placeholders() {
+        let alias = tracker("https://t.example/{cat}/rss?q={q}&c={cat}");
+        let url = alias.fill(&params(&[("cat", "41"), ("q", "some show")])).unwrap();
+        assert_eq!(url.as_str(), "https://t.example/41/rss?q=some%20show&c=41");
     }
 
-    // I wish there were a csv::ReaderWriter
-    let new_alias = Alias {name: cmd.alias.clone(), url: url};
-    aliases.insert(cmd.alias.clone(), new_alias);
+    #[test]
+    fn needs_a_value_for_every_placeholder() {
+        let alias = tracker("https://t.example/rss?cat={cat}&q={q}");
 
-    match alias_util::write_alias_db(config.data_dir.as_path(), aliases) {
-        Ok(()) => info!("Sucessfully updated alias db."),
-        Err(err) => error!("Could not update alias db: {}", err),
+        let err = alias.fill(&params(&[("cat", "41")])).unwrap_err().to_string();
+        assert!(err.contains("needs a value for q"), "{}", err);
+
+        let err = alias.fill(&[]).unwrap_err().to_string();
+        assert!(err.contains("needs a value for cat, q"), "{}", err);
+    }
+
+    #[test]
+    fn needs_a_placeholder_for_every_parameter() {
+        let alias = tracker("https://t.example/rss?q={q}");
+        let err = alias.fill(&params(&[("q", "x"), ("cat", "41")])).unwrap_err().to_string();
+        assert!(err.contains("has no placeholder {cat}"), "{}", err);
+    }
+
+    #[test]
+    fn encodes_reserved_characters() {
+        assert_eq!(percent_encode("AZaz09-._~"), "AZaz09-._~");
+        assert_eq!(percent_encode("a b&c=d/e?f#g"), "a%20b%26c%3Dd%2Fe%3Ff%23g");
+        assert_eq!(percent_encode("%{}+,:;"), "%25%7B%7D%2B%2C%3A%3B");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+
+        let alias = tracker("https://t.example/{q}/rss?q={q}");
+        let url = alias.fill(&params(&[("q", "a/b&passkey=x")])).unwrap();
+        assert_eq!(url.as_str(), "https://t.example/a%2Fb%26passkey%3Dx/rss?q=a%2Fb%26passkey%3Dx");
+    }
+
+    #[test]
+    fn keeps_encoded_braces_that_are_not_placeholders() {
+        let alias = tracker("https://t.example/rss?q=%7Bfoo&x=%7B%7D");
+        assert!(alias.placeholders().is_empty());
+        assert_eq!(alias.fill(&[]).unwrap().as_str(), "https://t.example/rss?q=%7Bfoo&x=%7B%7D");
+
+        let alias = tracker("https://t.example/rss?q=%7Bq%7D&x=%7B");
+        assert_eq!(alias.placeholders(), vec!["q"]);
+        let url = alias.fill(&params(&[("q", "{a}")])).unwrap();
+        assert_eq!(url.as_str(), "https://t.example/rss?q=%7Ba%7D&x=%7B");
+
+        // values that look like placeholders are not filled in again
+        let alias = tracker("https://t.example/rss?q={q}&a={a}");
+        let url = alias.fill(&params(&[("q", "{a}"), ("a", "x")])).unwrap();
+        assert_eq!(url.as_str(), "https://t.example/rss?q=%7Ba%7D&a=x");
+    }
+
+    fn store_with_feeds() -> MemoryStore {
+        let mut store = MemoryStore::default();
+        store.insert_alias(tracker("https://t.example/rss?q={q}")).unwrap();
+        let plain = Url::parse("https://plain.example/rss").unwrap();
+        store.insert_alias(Alias { name: "plain".to_string(), url: plain }).unwrap();
+
+        let feeds = Feeds::new();
+        let mut linked = Feed::new(Url::parse("https://t.example/rss?q=show").unwrap(),
+                                   vec!["720p".to_string()], &feeds);
+        linked.alias = Some("tracker:q=show".to_string());
+        store.insert_feed(linked).unwrap();
+
+        // has the url of the plain alias, but doesn't follow it
+        let unlinked = Feed::new(Url::parse("https://plain.example/rss").unwrap(), Vec::new(),
+                                 &store.list_feeds().unwrap());
+        store.insert_feed(unlinked).unwrap();
+        store
+    }
+
+    // the feeds are told apart by their filters, since urls and aliases change
+    fn linked_feed(store: &mut MemoryStore) -> Feed {
+        store.list_feeds().unwrap().into_iter().find(|f| !f.filters.is_empty()).unwrap()
+    }
+
+    fn unlinked_feed(store: &mut MemoryStore) -> Feed {
+        store.list_feeds().unwrap().into_iter().find(|f| f.filters.is_empty()).unwrap()
+    }
+
+    fn set(name: &str, url: &str, cascade: bool, store: &mut MemoryStore) {
+        let cmd = RTAliasSet { alias: name.to_string(), url: url.to_string(), cascade: cascade };
+        set_alias(cmd, store);
+    }
+
+    #[test]
+    fn feeds_follow_a_changed_alias() {
+        let mut store = store_with_feeds();
+
+        set("tracker", "https://t2.example/rss?q={q}&x=1", false, &mut store);
+        let feed = linked_feed(&mut store);
+        assert_eq!(feed.url.as_str(), "https://t2.example/rss?q=show&x=1");
+        assert_eq!(feed.alias.as_ref().unwrap(), "tracker:q=show");
+    }
+
+    #[test]
+    fn cascade_rewrites_feeds_with_the_old_url() {
+        let mut store = store_with_feeds();
+
+        set("plain", "https://plain2.example/rss", false, &mut store);
+        assert_eq!(unlinked_feed(&mut store).url.as_str(), "https://plain.example/rss");
+
+        set("plain", "https://plain.example/rss", false, &mut store);
+        set("plain", "https://plain3.example/rss", true, &mut store);
+        let feed = unlinked_feed(&mut store);
+        assert_eq!(feed.url.as_str(), "https://plain3.example/rss");
+        // --cascade changes the url, but doesn't make the feed follow the alias
+        assert_eq!(feed.alias, None);
+        assert_eq!(store.get_alias("plain").unwrap().unwrap().url, feed.url);
+    }
+
+    #[test]
+    fn feeds_follow_a_renamed_alias() {
+        let mut store = store_with_feeds();
+
+        rename_alias(RTAliasRename { old: "tracker".to_string(), new: "renamed".to_string() },
+                     &mut store);
+        assert!(store.get_alias("tracker").unwrap().is_none());
+        assert!(store.get_alias("renamed").unwrap().is_some());
+
+        let feed = linked_feed(&mut store);
+        assert_eq!(feed.alias.as_ref().unwrap(), "renamed:q=show");
+        assert_eq!(feed.url.as_str(), "https://t.example/rss?q=show");
+
+        // renaming onto an existing alias changes nothing
+        rename_alias(RTAliasRename { old: "renamed".to_string(), new: "plain".to_string() },
+                     &mut store);
+        assert!(store.get_alias("renamed").unwrap().is_some());
+        assert_eq!(linked_feed(&mut store).alias.as_ref().unwrap(), "renamed:q=show");
+    }
+
+    #[test]
+    fn removing_a_followed_alias_needs_unlink() {
+        let mut store = store_with_feeds();
+
+        remove_alias(RTAliasRm { alias: "tracker".to_string(), unlink: false }, &mut store);
+        assert!(store.get_alias("tracker").unwrap().is_some());
+        assert!(linked_feed(&mut store).alias.is_some());
+
+        remove_alias(RTAliasRm { alias: "tracker".to_string(), unlink: true }, &mut store);
+        assert!(store.get_alias("tracker").unwrap().is_none());
+        let feed = linked_feed(&mut store);
+        assert_eq!(feed.alias, None);
+        assert_eq!(feed.url.as_str(), "https://t.example/rss?q=show");
+
+        // aliases no feed follows are removed without --unlink
+        remove_alias(RTAliasRm { alias: "plain".to_string(), unlink: false }, &mut store);
+        assert!(store.get_alias("plain").unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_alias_refs() {
+        assert_eq!(AliasRef::parse("tracker").unwrap(),
+                   AliasRef { name: "tracker".to_string(), params: Vec::new() });
+
+        let alias_ref = AliasRef::parse("tracker:cat=41,q=a=b").unwrap();
+        assert_eq!(alias_ref.params, params(&[("cat", "41"), ("q", "a=b")]));
+        assert_eq!(alias_ref.to_string(), "tracker:cat=41,q=a=b");
+
+        assert!(AliasRef::parse("tracker:cat").is_err());
+        assert!(AliasRef::parse("tracker:=41").is_err());
     }
 }
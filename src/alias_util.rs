@@ -1,43 +1,35 @@
 use csv;
 
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs::{File, OpenOptions};
-use std::io::Write;
 
 use alias::{AliasRecord, Aliases};
+use db_util;
 
 
-const ALIAS_DB_FILENAME: &str = "aliases.csv";
+pub const ALIAS_DB_FILENAME: &str = "aliases.csv";
 
-/// Opens for read write and create because it's simpler
+/// Opens for read write and create because it's simpler. Only used for writing.
 fn open_or_create_alias_db(data_dir: &Path) -> Result<File, Box<Error>> {
-    trace!("Opening alias file.");
-
-    let mut db_path = data_dir.to_path_buf();
-    db_path.push(ALIAS_DB_FILENAME);
-
-    if !db_path.exists() {
-        warn!("Alias db not found at {}, creating.", db_path.to_string_lossy());
-    }
-    else {
-        trace!("Alias db found at {}.", db_path.to_string_lossy());
-    }
-
     Ok(OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open(db_path)?)
+        .truncate(false)
+        .open(db_util::db_path(data_dir, ALIAS_DB_FILENAME, "Alias"))?)
 }
 
-/// Read alias db or create if it does not exist.
+/// Read alias db, or return no aliases if it does not exist.
 pub fn read_alias_db(data_dir: &Path) -> Result<Aliases, Box<Error>> {
     debug!("Reading alias db.");
 
     let mut aliases = Aliases::new();
 
-    let db_file = open_or_create_alias_db(data_dir)?;
+    let db_file = match db_util::open_existing_db(data_dir, ALIAS_DB_FILENAME, "Alias")? {
+        Some(file) => file,
+        None => return Ok(aliases),
+    };
 
     let mut reader = csv::Reader::from_reader(db_file);
 
@@ -48,7 +40,7 @@ pub fn read_alias_db(data_dir: &Path) -> Result<Aliases, Box<Error>> {
         aliases.insert(alias.name.clone(), alias);
     }
 
-    return Ok(aliases);
+    Ok(aliases)
 }
 
 /// Write alias db or create if it does not exist.
@@ -66,14 +58,14 @@ pub fn write_alias_db(data_dir: &Path, mut aliases: Aliases) -> Result<(), Box<E
         }
     }
     
-    // there should be a better way of doing this
-    let result = db_file.write_all(&mut buf).map_err(|e| {
-        error!("Error writing to file. DATA MAY BE CORRUPTED!");
-        e
-    })
-    .and_then(|_| {
-        db_file.set_len(buf.len() as u64)
-    });
-
-    Ok(result?)
+    db_util::overwrite_db(&mut db_file, &buf)
+}
+
+/// Copy aliases.csv aside, see `db_util::backup_file`. Returns `None` if there is nothing to back
+/// up.
+pub fn backup_alias_db(data_dir: &Path, tag: &str) -> Result<Option<PathBuf>, Box<Error>> {
+    let mut db_path = data_dir.to_path_buf();
+    db_path.push(ALIAS_DB_FILENAME);
+
+    db_util::backup_file(&db_path, tag)
 }
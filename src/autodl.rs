@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+
+use reqwest::Url;
+
+use import::{Imported, ImportedFeed};
+use rule_util;
+
+// Settings that don't change which releases a filter matches. The sites are ignored since the
+// feed to use is given on the command line.
+const IGNORED_KEYS: &[&str] = &[
+    "enabled", "use-regex", "match-sites", "except-sites", "upload-type", "upload-watch-dir",
+    "upload-ftp-path", "upload-command", "upload-args", "upload-dyndir", "download-duplicates",
+    "max-downloads", "max-downloads-per", "priority",
+];
+
+// Lists of alternatives that all have to match, e.g. one of the shows in one of the resolutions.
+const CHOICE_KEYS: &[&str] = &["shows", "resolutions", "sources", "encoders", "containers"];
+
+/// A `[filter name]` section of autodl.cfg, with its settings in order.
+struct AutodlFilter {
+    name: String,
+    settings: Vec<(String, String)>,
+}
+
+impl AutodlFilter {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.settings.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v.as_str())
+    }
+
+    fn is_set(&self, key: &str) -> bool {
+        self.get(key).map_or(false, |v| v == "true" || v == "1" || v == "yes")
+    }
+}
+
+fn read_filters(contents: &str) -> Result<Vec<AutodlFilter>, Box<Error>> {
+    let mut filters = Vec::new();
+    // whether we are in a filter section, as opposed to e.g. `[options]`
+    let mut in_filter = false;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            let section = line[1..line.len() - 1].trim();
+            in_filter = section == "filter" || section.starts_with("filter ");
+            if in_filter {
+                filters.push(AutodlFilter {
+                    name: section["filter".len()..].trim().to_string(),
+                    settings: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let (key, value) = match line.find('=') {
+            Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+            None => return Err(Box::new(io::Error::new(ErrorKind::InvalidData,
+                format!("line {}: expected a [section] or key = value", i + 1)))),
+        };
+
+        match filters.last_mut() {
+            Some(filter) if in_filter && !value.is_empty() => {
+                filter.settings.push((key.to_lowercase(), value.to_string()));
+            },
+            _ => {},
+        }
+    }
+
+    Ok(filters)
+}
+
+/// Read the filters of autodl-irssi's autodl.cfg, as used by ruTorrent's autodl plugin, and
+/// apply them to `url` since autodl filters aren't tied to a feed. Each filter becomes a feed
+/// with one filter per combination of release pattern, show, resolution and so on. Filters with
+/// exclusions or other conditions that can't be expressed are left out, since importing them
+/// would download more than autodl does.
+pub fn parse_autodl(contents: &str, url: &Url) -> Result<Imported, Box<Error>> {
+    let mut imported = Imported::default();
+
+    for filter in read_filters(contents)? {
+        match translate_filter(&filter) {
+            Ok(filters) => imported.feeds.push(ImportedFeed {
+                url: url.clone(),
+                filters: filters,
+                last_update: None,
+                alias: None,
+            }),
+            Err(reason) => imported.unsupported.push(format!("filter {}: {}", filter.name, reason)),
+        }
+    }
+
+    Ok(imported)
+}
+
+fn split_list(value: &str) -> Vec<&str> {
+    value.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).collect()
+}
+
+/// The filters of an autodl filter, or why it can't be imported.
+fn translate_filter(filter: &AutodlFilter) -> Result<Vec<String>, String> {
+    if filter.get("enabled").is_some() && !filter.is_set("enabled") {
+        return Err("it is disabled".to_string());
+    }
+
+    let mut releases = vec![Vec::new()];
+    let mut choices: Vec<Vec<Vec<String>>> = Vec::new();
+    let mut conditions = Vec::new();
+
+    for &(ref key, ref value) in &filter.settings {
+        match key.as_str() {
+            "match-releases" => {
+                let mut patterns = Vec::new();
+                for pattern in split_list(value) {
+                    if filter.is_set("use-regex") {
+                        let words = rule_util::regex_filters(pattern).ok_or_else(|| {
+                            format!("the regular expression {} can't be translated", pattern)
+                        })?;
+                        patterns.extend(words);
+                    }
+                    else {
+                        patterns.push(rule_util::wildcard_words(pattern));
+                    }
+                }
+                releases = patterns;
+            },
+            "min-size" | "max-size" => {
+                let op = if key == "min-size" { ">=" } else { "<=" };
+                let condition = rule_util::size_condition(op, value)
+                    .ok_or_else(|| format!("the size {} can't be translated", value))?;
+                conditions.push(condition);
+            },
+            k if CHOICE_KEYS.contains(&k) => {
+                choices.push(split_list(value).into_iter().map(rule_util::wildcard_words).collect());
+            },
+            k if IGNORED_KEYS.contains(&k) => {},
+            k => return Err(format!("{} can't be translated", k)),
+        }
+    }
+
+    if filter.get("match-releases").is_none() && filter.get("shows").is_none() {
+        return Err("it has no release patterns or shows".to_string());
+    }
+
+    Ok(releases.into_iter()
+        .flat_map(|release| rule_util::combine(release, &choices))
+        .map(|mut words| {
+            words.extend(conditions.iter().cloned());
+            words.join(" ")
+        })
+        .collect())
+}
@@ -1,3 +1,8 @@
+use add::Since;
+use list::{ListKind, SortKey};
+use output::OutputFormat;
+use store::Backend;
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "rss-torrent")]
 pub struct RTArgs {
@@ -10,8 +15,14 @@ pub struct RTArgs {
     /// repetitions of this flag will produce more output.
     pub verbosity: u64,
 
+    #[structopt(long = "show-secrets")]
+    /// Do not mask passkeys and other secrets in urls when logging. Only use this for local
+    /// debugging.
+    pub show_secrets: bool,
+
     #[structopt(short = "c", long = "config")]
-    /// Override the default configuration directory.
+    /// A config file that overrides the values from all other config files. Individual values can
+    /// also be overridden with RSS_TORRENT_<KEY> environment variables, e.g. RSS_TORRENT_DATA_DIR.
     pub config: Option<String>,
 
     #[structopt(subcommand)]
@@ -26,43 +37,185 @@ pub enum RTCommand {
     Add(RTAdd),
 
     #[structopt(name = "alias")]
-    /// Add an alias for a rss feed url.
+    /// Manage aliases for rss feed urls.
     Alias(RTAlias),
 
     #[structopt(name = "update")]
     /// Run an update, fetching and parsing feeds and adding torrents not seen since the last update.
-    Update,
+    Update(RTUpdate),
 
     #[structopt(name = "delete")]
     /// Delete a feed from the database. `url_or_alias` is optional, but if the `--filters` option
     /// is used without `--all` or a `url_or_alias`, this command does nothing.
-    Delete(RTDelete)
+    Delete(RTDelete),
+
+    #[structopt(name = "edit")]
+    /// Change the filters or other settings of a feed, keeping its id, history and last update
+    /// time.
+    Edit(RTEdit),
+
+    #[structopt(name = "pause")]
+    /// Stop updating a feed, or all feeds with a given url or alias, keeping their filters and
+    /// last update time.
+    Pause(RTPause),
+
+    #[structopt(name = "resume")]
+    /// Start updating paused feeds again.
+    Resume(RTResume),
+
+    #[structopt(name = "show")]
+    /// Show the details of a feed, or of all feeds with a given url or alias.
+    Show(RTShow),
+
+    #[structopt(name = "preview")]
+    /// Fetch a feed and show its items and which of them the given filters match, without adding
+    /// the feed.
+    Preview(RTPreview),
+
+    #[structopt(name = "list")]
+    /// List all feeds, or all aliases with `list aliases`.
+    List(RTList),
+
+    #[structopt(name = "status")]
+    /// Summarize the health of all feeds. Exits with a non-zero status if any feed is failing or
+    /// hasn't matched anything recently, or if no update has fetched any feed yet.
+    Status(RTStatus),
+
+    #[structopt(name = "import")]
+    /// Import feeds and aliases from another tool.
+    Import(RTImport),
+
+    #[structopt(name = "export")]
+    /// Export feeds and aliases for another tool.
+    Export(RTExport),
+
+    #[structopt(name = "config")]
+    /// Show, check or create the configuration file.
+    Config(RTConfigCmd),
+
+    #[structopt(name = "db")]
+    /// Database maintenance.
+    Db(RTDb),
 }
 
 #[derive(StructOpt, Debug)]
 pub struct RTAdd {
-    /// A RSS feed url or an existing alias.
+    /// A RSS feed url or an existing alias. If the alias's url has placeholders, give their values
+    /// after a colon, e.g. `tracker:cat=41,q=foo`.
     pub url_or_alias: String,
 
     /// A list of filters that each item in the feed will be matched against. The search checks
     /// that each word (indepedently) of the filter is somewhere in the title of the item, and
-    /// if all of them are, the item matches.
+    /// if all of them are, the item matches. Words of the form `seeders>=5`, `size<4GiB`,
+    /// `category:5040` or `imdbid:tt0944947` are checked against the item's torznab attributes
+    /// instead of the title.
     pub filters: Vec<String>,
+
+    #[structopt(long = "link-alias")]
+    /// Keep following the alias given as `url_or_alias`, so that changing the alias's url later
+    /// also changes this feed's url.
+    pub link_alias: bool,
+
+    #[structopt(long = "since", conflicts_with = "initial_sync")]
+    /// Only add items published after this time: `now`, a RFC 3339 timestamp like
+    /// `2018-06-01T00:00:00Z`, or how long ago, like `30m`, `12h`, `3d` or `2w`. By default every
+    /// matching item already in the feed is added on the next update.
+    pub since: Option<Since>,
+
+    #[structopt(long = "backfill", conflicts_with = "since")]
+    /// Fetch the feed now and only add the N newest matching items on the next update.
+    pub backfill: Option<usize>,
+
+    #[structopt(long = "initial-sync", conflicts_with = "backfill")]
+    /// Fetch the feed now and only add items published after its newest item, so nothing already
+    /// in the feed is added.
+    pub initial_sync: bool,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct RTAlias {
+    #[structopt(subcommand)]
+    pub cmd: RTAliasCommand,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum RTAliasCommand {
+    #[structopt(name = "set")]
+    /// Add an alias, or change the url of an existing one. Feeds added with `--link-alias` follow
+    /// the change.
+    Set(RTAliasSet),
+
+    #[structopt(name = "rm")]
+    /// Remove an alias.
+    Rm(RTAliasRm),
+
+    #[structopt(name = "rename")]
+    /// Rename an alias. Feeds following it follow the new name.
+    Rename(RTAliasRename),
+
+    #[structopt(name = "list")]
+    /// List all aliases.
+    List(RTAliasList),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTAliasSet {
     /// An alias for this url which can be used in `add` and `delete` commands.
     pub alias: String,
 
-    /// The url of an RSS feed.
+    /// The url of an RSS feed. It can contain placeholders like `{cat}`, which are filled in when
+    /// the alias is used, e.g. `https://example.com/rss?cat={cat}&q={q}` used as
+    /// `tracker:cat=41,q=foo`.
     pub url: String,
+
+    #[structopt(long = "cascade")]
+    /// Also change the url of every feed that has the alias's old url, even if it doesn't follow
+    /// the alias.
+    pub cascade: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTAliasRm {
+    /// The alias to remove.
+    pub alias: String,
+
+    #[structopt(long = "unlink")]
+    /// Remove the alias even if feeds follow it. They keep its current url.
+    pub unlink: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTAliasRename {
+    /// The current name of the alias.
+    pub old: String,
+
+    /// The new name. There must not be an alias with this name already.
+    pub new: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTAliasList {
+    #[structopt(long = "format", default_value = "table")]
+    /// Output format, one of `table`, `json` or `csv`.
+    pub format: OutputFormat,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTUpdate {
+    #[structopt(long = "dry-run")]
+    /// Fetch and match the feeds as usual, but only print which items would be added and the
+    /// command that would add them. Nothing is downloaded, run or written to the database.
+    pub dry_run: bool,
+
+    #[structopt(long = "format", default_value = "table")]
+    /// Output format of `--dry-run`, one of `table`, `json` or `csv`.
+    pub format: OutputFormat,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct RTDelete {
-    /// Delete all feeds from the database with the given url, or a url that matches a given
-    /// alias's url.
+    /// Delete the feed with the given id, or all feeds from the database with the given url, or a
+    /// url that matches a given alias's url.
     pub url_or_alias: Option<String>,
 
     #[structopt(long = "dry-run")]
@@ -79,3 +232,314 @@ pub struct RTDelete {
     #[structopt(long = "filters")]
     pub filters: Vec<String>
 }
+
+#[derive(StructOpt, Debug)]
+pub struct RTEdit {
+    /// The id of the feed to edit, or its url or alias if only one feed has it.
+    pub id_or_url: String,
+
+    #[structopt(long = "add-filter")]
+    /// Add these filters.
+    pub add_filters: Vec<String>,
+
+    #[structopt(long = "remove-filter")]
+    /// Remove these filters. It is an error if the feed doesn't have one of them.
+    pub remove_filters: Vec<String>,
+
+    #[structopt(long = "set-filters", conflicts_with = "clear_filters")]
+    /// Replace all of the feed's filters with these. `--remove-filter` and `--add-filter` are
+    /// applied afterwards.
+    pub set_filters: Vec<String>,
+
+    #[structopt(long = "clear-filters")]
+    /// Remove all of the feed's filters, so every item matches.
+    pub clear_filters: bool,
+
+    #[structopt(long = "url")]
+    /// Change the feed's url to this url or alias. The feed stops following the alias it
+    /// followed, if any.
+    pub url: Option<String>,
+
+    #[structopt(long = "link-alias", requires = "url")]
+    /// Follow the alias given with `--url`, like `add --link-alias`.
+    pub link_alias: bool,
+
+    #[structopt(long = "unlink-alias", conflicts_with = "link_alias")]
+    /// Stop following an alias, keeping the current url.
+    pub unlink_alias: bool,
+
+    #[structopt(long = "since")]
+    /// Reset the last update time, like `add --since`.
+    pub since: Option<Since>,
+
+    #[structopt(long = "dry-run")]
+    /// Do not modify the database, only print the changes.
+    pub dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTPause {
+    /// Pause the feed with the given id, or all feeds with the given url, or a url that matches a
+    /// given alias's url.
+    pub url_or_alias: Option<String>,
+
+    #[structopt(long = "dry-run")]
+    /// Do not modify the database, only print out the feeds that would be paused.
+    pub dry_run: bool,
+
+    #[structopt(long = "all")]
+    /// Search all feeds that match the given filters rather than just ones with the same
+    /// url_or_alias.
+    pub search_all: bool,
+
+    /// Pause only feeds with filters matching the ones passed.
+    #[structopt(long = "filters")]
+    pub filters: Vec<String>
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTResume {
+    /// Resume the feed with the given id, or all feeds with the given url, or a url that matches
+    /// a given alias's url.
+    pub url_or_alias: Option<String>,
+
+    #[structopt(long = "catch-up", conflicts_with = "skip_missed")]
+    /// Add the matching items that were published while the feed was paused on the next update.
+    pub catch_up: bool,
+
+    #[structopt(long = "skip-missed")]
+    /// Skip the items that were published while the feed was paused, and only add items
+    /// published from now on.
+    pub skip_missed: bool,
+
+    #[structopt(long = "dry-run")]
+    /// Do not modify the database, only print out the feeds that would be resumed.
+    pub dry_run: bool,
+
+    #[structopt(long = "all")]
+    /// Search all feeds that match the given filters rather than just ones with the same
+    /// url_or_alias.
+    pub search_all: bool,
+
+    /// Resume only feeds with filters matching the ones passed.
+    #[structopt(long = "filters")]
+    pub filters: Vec<String>
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTShow {
+    /// A feed id, or a url or alias to show all feeds with that url.
+    pub id_or_url: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTPreview {
+    /// A RSS feed url or an existing alias, like in `add`. Leave it out when using `--file`.
+    #[structopt(required_unless = "file", conflicts_with = "file")]
+    pub url_or_alias: Option<String>,
+
+    /// Filters to match the items against, like in `add`.
+    pub filters: Vec<String>,
+
+    #[structopt(raw(last = "true"))]
+    /// More filters, given after `--`. This is how filters are given with `--file`, e.g.
+    /// `preview --file feed.xml -- "some show"`.
+    pub trailing_filters: Vec<String>,
+
+    #[structopt(long = "file")]
+    /// Read the feed from this file instead of fetching it, e.g. a saved copy of the feed.
+    pub file: Option<String>,
+
+    #[structopt(long = "format", default_value = "table")]
+    /// Output format, one of `table`, `json` or `csv`.
+    pub format: OutputFormat,
+
+    #[structopt(long = "no-highlight")]
+    /// Do not highlight the words of the filters in the titles. Highlighting is also turned off
+    /// by setting NO_COLOR.
+    pub no_highlight: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTList {
+    #[structopt(default_value = "feeds")]
+    /// What to list, either `feeds` or `aliases`.
+    pub kind: ListKind,
+
+    #[structopt(long = "format", default_value = "table")]
+    /// Output format, one of `table`, `json` or `csv`.
+    pub format: OutputFormat,
+
+    #[structopt(long = "sort", default_value = "url")]
+    /// Sort by `id`, `url`, `alias`, `last-update` or `failures`. Aliases can only be sorted by
+    /// name or `url`.
+    pub sort: SortKey,
+
+    #[structopt(long = "reverse")]
+    /// Reverse the sort order.
+    pub reverse: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTStatus {
+    #[structopt(long = "days", default_value = "30")]
+    /// Report feeds that haven't had an item match their filters in this many days, counting
+    /// from when they were added if they never had a match.
+    pub days: u64,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTImport {
+    #[structopt(subcommand)]
+    pub cmd: RTImportCommand,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum RTImportCommand {
+    #[structopt(name = "opml")]
+    /// Import feeds from an OPML file, e.g. one exported by a feed reader or by `export opml`.
+    /// Feeds and aliases that already exist are skipped.
+    Opml(RTImportFile),
+
+    #[structopt(name = "legacy")]
+    /// Import the feeds and aliases of the old Python rss_torrent script. The current databases
+    /// are backed up first.
+    Legacy(RTImportLegacy),
+
+    #[structopt(name = "flexget")]
+    /// Import the rss tasks of a FlexGet config. `series` shows and `regexp` accept patterns
+    /// become filters. Tasks with anything that can't be expressed as filters, like reject
+    /// patterns, are reported and skipped.
+    Flexget(RTImportFile),
+
+    #[structopt(name = "qbittorrent")]
+    /// Import qBittorrent's RSS download rules from its `rss/download_rules.json`. Rules with
+    /// "must not contain" patterns or episode filters are reported and skipped.
+    Qbittorrent(RTImportFile),
+
+    #[structopt(name = "autodl")]
+    /// Import the filters of autodl-irssi's autodl.cfg, as used by ruTorrent's autodl plugin.
+    /// Filters with exclusions or other settings that can't be expressed are reported and
+    /// skipped. The filters of ruTorrent's own RSS plugin are not supported.
+    Autodl(RTImportAutodl),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTImportFile {
+    /// The file to import.
+    pub file: String,
+
+    #[structopt(long = "dry-run")]
+    /// Do not modify the database, only print what would be imported.
+    pub dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTImportLegacy {
+    /// The directory with the old feeds.csv (or database.csv) and aliases.csv.
+    pub dir: String,
+
+    #[structopt(long = "dry-run")]
+    /// Do not modify the database, only print what would be imported.
+    pub dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTImportAutodl {
+    /// The autodl.cfg file to import.
+    pub file: String,
+
+    #[structopt(long = "feed")]
+    /// The RSS feed url or alias to apply the filters to, since autodl filters aren't tied to a
+    /// feed.
+    pub feed: String,
+
+    #[structopt(long = "dry-run")]
+    /// Do not modify the database, only print what would be imported.
+    pub dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTExport {
+    #[structopt(subcommand)]
+    pub cmd: RTExportCommand,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum RTExportCommand {
+    #[structopt(name = "opml")]
+    /// Export all feeds and aliases as OPML. Filters and aliases are kept in attributes that
+    /// other programs ignore, so importing the file again gives back the same feeds.
+    Opml(RTExportOpml),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTExportOpml {
+    #[structopt(short = "o", long = "output")]
+    /// Write to this file instead of stdout. The urls are not redacted.
+    pub output: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTConfigCmd {
+    #[structopt(subcommand)]
+    pub cmd: RTConfigCommand,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum RTConfigCommand {
+    #[structopt(name = "show")]
+    /// Print the configuration in use, which files were merged to make it and which were skipped
+    /// and why.
+    Show(RTConfigShow),
+
+    #[structopt(name = "check")]
+    /// Check that a config file is valid. Syntax errors include the line and column.
+    Check(RTConfigCheck),
+
+    #[structopt(name = "init")]
+    /// Write a commented config file to $XDG_CONFIG_HOME/rss-torrent/config.toml (by default
+    /// ~/.config/rss-torrent/config.toml) and create the data directory it uses.
+    Init(RTConfigInit),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTConfigShow {
+    #[structopt(long = "origin")]
+    /// Show which file or environment variable each value was set by.
+    pub origin: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTConfigCheck {
+    /// The config file to check.
+    pub file: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTConfigInit {
+    #[structopt(long = "force")]
+    /// Overwrite the config file if it already exists.
+    pub force: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTDb {
+    #[structopt(subcommand)]
+    pub cmd: RTDbCommand,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum RTDbCommand {
+    #[structopt(name = "migrate")]
+    /// Copy all feeds, aliases and history into another storage backend. The target must be
+    /// empty. Afterwards, set `storage` in the config file to use the new backend.
+    Migrate(RTDbMigrate),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RTDbMigrate {
+    #[structopt(long = "to")]
+    /// The backend to migrate to, either `csv` or `sqlite`. Data is read from the other one.
+    pub to: Backend,
+}
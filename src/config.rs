@@ -1,161 +1,498 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Read, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use toml;
 
-const DEFAULT_DATA_DIR: &str = "/usr/local/share/rss-torrent/";
+use store::Backend;
+
+// Only used if there's no home directory, or if it already has data from before the XDG dirs were
+// used. It isn't writable by normal users.
+const LEGACY_DATA_DIR: &str = "/usr/local/share/rss-torrent/";
 
 const CONFIG_ENV_VAR: &str = "RSS_TORRENT_CONFIG";
 
-const HOME_CONFIG_RELPATH: &str = ".config/rss_torrent.toml";
+// Individual keys can be set with e.g. RSS_TORRENT_DATA_DIR.
+const KEY_ENV_VAR_PREFIX: &str = "RSS_TORRENT_";
+
+// Relative to $XDG_CONFIG_HOME, $XDG_DATA_HOME and $XDG_STATE_HOME, or to their defaults in the
+// home directory.
+const XDG_CONFIG_RELPATH: &str = "rss-torrent/config.toml";
+const XDG_DATA_RELPATH: &str = "rss-torrent";
+const XDG_STATE_RELPATH: &str = "rss-torrent";
+
+// Deprecated in favor of XDG_CONFIG_RELPATH, but still read.
+const LEGACY_HOME_CONFIG_RELPATH: &str = ".config/rss_torrent.toml";
 const USR_LOCAL_CONFIG_PATH: &str = "/usr/local/etc/rss_torrent.toml";
 const ETC_CONFIG_PATH: &str = "/etc/rss_torrent.toml";
 
 // TODO use failure crate instead of a boxed error
 
-/// `data_dir` is the directory where the databases are stored. `torrent_file_cache_dir` is an
-/// optional directory where downloaded torrent files will be stored.
+/// `data_dir` is the directory where the databases are stored, and `state_dir` is where the
+/// history and other records of past runs are kept. `torrent_file_cache_dir` is an optional
+/// directory where downloaded torrent files will be stored. `redact_keys` are url query
+/// keys that are masked in log output, in addition to the defaults in `redact`. `storage` selects
+/// whether the databases are csv files or a sqlite database.
 #[derive(Debug, Clone)]
 pub struct RTConfig {
     pub data_dir: PathBuf,
+    pub state_dir: PathBuf,
+    pub storage: Backend,
     pub torrent_add_command: String,
     pub torrent_add_args: Vec<String>,
     pub torrent_file_cache_dir: Option<PathBuf>,
+    pub redact_keys: Vec<String>,
 }
 
 impl Default for RTConfig {
     fn default() -> RTConfig {
         RTConfig {
-            data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+            data_dir: PathBuf::from(LEGACY_DATA_DIR),
+            state_dir: PathBuf::from(LEGACY_DATA_DIR),
+            storage: Backend::default(),
             torrent_add_command: "transmission-remote".to_string(),
             torrent_add_args: vec!["-a".to_string(), "_TORRENT_PATH".to_string(), "-sr".to_string(), 50.to_string()],
             torrent_file_cache_dir: None,
+            redact_keys: Vec::new(),
         }
     }
 }
 
-// Deserialize to this struct, then convert to actual RTConfig struct
-#[derive(Debug, Clone, Deserialize)]
+/// Where the config came from: the files that were merged in order of increasing precedence, the
+/// files and environment variables that were skipped along with the reason, and which file or
+/// environment variable each key was last set by. Keys missing from `origins` have their default
+/// value. `invalid` is why the config should not be used: an explicitly given file or an
+/// environment variable that is invalid, or a merged config that is. `using_defaults` is set in
+/// the last case, since the defaults are used instead.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigReport {
+    pub sources: Vec<PathBuf>,
+    pub skipped: Vec<(PathBuf, String)>,
+    pub skipped_vars: Vec<(String, String)>,
+    pub origins: BTreeMap<String, String>,
+    pub invalid: Option<String>,
+    pub using_defaults: bool,
+}
+
+impl ConfigReport {
+    pub fn origin(&self, key: &str) -> &str {
+        self.origins.get(key).map_or("default", |o| o.as_str())
+    }
+}
+
+// Deserialize to this struct, then convert to actual RTConfig struct. Everything is optional, and
+// an empty file gives the same config as having no file at all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RTConfigValues {
-    data_dir: String,
-    torrent_add_command: String,
-    torrent_add_args: Vec<String>,
+    data_dir: Option<String>,
+    state_dir: Option<String>,
+    storage: Option<String>,
+    torrent_add_command: Option<String>,
+    torrent_add_args: Option<Vec<String>>,
     torrent_file_cache_dir: Option<String>,
+    redact_keys: Option<Vec<String>>,
 }
 
 impl RTConfigValues {
+    pub fn from_file(mut f: File) -> Result<RTConfigValues, Box<Error>> {
+        let mut contents = String::new();
+        f.read_to_string(&mut contents)?;
+
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Values from `RSS_TORRENT_<KEY>` environment variables, one per variable that is set so
+    /// each can be merged with its own origin, or why the variable is invalid. Lists are either a
+    /// TOML array or separated by whitespace.
+    pub fn from_env() -> Vec<(String, Result<RTConfigValues, String>)> {
+        let keys = ["data_dir", "state_dir", "storage", "torrent_add_command", "torrent_add_args",
+                    "torrent_file_cache_dir", "redact_keys"];
+
+        let mut vars = Vec::new();
+        for &key in &keys {
+            let var = format!("{}{}", KEY_ENV_VAR_PREFIX, key.to_uppercase());
+            let value = match env::var(&var) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let mut values = RTConfigValues::default();
+            match key {
+                "data_dir" => values.data_dir = Some(value),
+                "state_dir" => values.state_dir = Some(value),
+                "storage" => values.storage = Some(value),
+                "torrent_add_command" => values.torrent_add_command = Some(value),
+                "torrent_file_cache_dir" => values.torrent_file_cache_dir = Some(value),
+                _ => {
+                    let list = match parse_env_list(&value) {
+                        Ok(list) => list,
+                        Err(err) => {
+                            vars.push((var, Err(err.to_string())));
+                            continue;
+                        }
+                    };
+
+                    if key == "torrent_add_args" {
+                        values.torrent_add_args = Some(list);
+                    }
+                    else {
+                        values.redact_keys = Some(list);
+                    }
+                }
+            }
+
+            vars.push((var, Ok(values)));
+        }
+
+        vars
+    }
+
+    /// Override every value that is set in `other`, recording `origin` as where it came from.
+    pub fn merge(&mut self, other: RTConfigValues, origin: &str,
+                 origins: &mut BTreeMap<String, String>) {
+        merge_value(&mut self.data_dir, other.data_dir, "data_dir", origin, origins);
+        merge_value(&mut self.state_dir, other.state_dir, "state_dir", origin, origins);
+        merge_value(&mut self.storage, other.storage, "storage", origin, origins);
+        merge_value(&mut self.torrent_add_command, other.torrent_add_command,
+                    "torrent_add_command", origin, origins);
+        merge_value(&mut self.torrent_add_args, other.torrent_add_args, "torrent_add_args",
+                    origin, origins);
+        merge_value(&mut self.torrent_file_cache_dir, other.torrent_file_cache_dir,
+                    "torrent_file_cache_dir", origin, origins);
+        merge_value(&mut self.redact_keys, other.redact_keys, "redact_keys", origin, origins);
+    }
+
     // Not sure if I should really be using io::Error here but these are io errors...
     pub fn to_config(self) -> Result<RTConfig, io::Error> {
-        let data_dir = PathBuf::from(&self.data_dir);
-        let torrent_file_cache_dir = self.torrent_file_cache_dir.map(PathBuf::from);
+        let defaults = RTConfig::default();
 
-        if !data_dir.exists() {
-            return Err(io::Error::new(ErrorKind::NotFound,
-                                      format!("Data directory not found: {}", data_dir.to_string_lossy())));
-        }
-        if !data_dir.is_dir() {
-            return Err(io::Error::new(ErrorKind::InvalidData,
-                                      format!("Data directory is not a directory: {}",
-                                              data_dir.to_string_lossy())));
-        }
+        // configured directories have to exist already, the default ones are created
+        let data_dir = match self.data_dir {
+            Some(dir) => {
+                let dir = PathBuf::from(dir);
+                check_dir(&dir, "Data")?;
+                dir
+            },
+            None => default_data_dir()?,
+        };
 
-        if let Some(ref cache_dir) = torrent_file_cache_dir {
-            if !cache_dir.exists() {
-                return Err(io::Error::new(ErrorKind::NotFound,
-                                          format!("Torrent cache directory not found: {}",
-                                                  cache_dir.to_string_lossy())));
-            }
+        let state_dir = match self.state_dir {
+            Some(dir) => {
+                let dir = PathBuf::from(dir);
+                check_dir(&dir, "State")?;
+                dir
+            },
+            None => default_state_dir(&data_dir)?,
+        };
 
-            if !cache_dir.is_dir() {
-                return Err(io::Error::new(ErrorKind::InvalidData,
-                                          format!("Torrent cache directory is not a directory: {}",
-                                                  cache_dir.to_string_lossy())));
-            }
+        let torrent_file_cache_dir = self.torrent_file_cache_dir.map(PathBuf::from);
+        if let Some(ref cache_dir) = torrent_file_cache_dir {
+            check_dir(cache_dir, "Torrent cache")?;
         }
 
+        let storage = match self.storage {
+            Some(s) => s.parse().map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            None => Backend::default(),
+        };
+
         // TODO could check that torrent command is also valid, but that there isn't anything in
         // stdlib that searches the path for you. So we'll leave handling that error to the actual
         // std::process::Command result.
 
         Ok(RTConfig {
             data_dir: data_dir,
-            torrent_add_command: self.torrent_add_command,
-            torrent_add_args: self.torrent_add_args,
+            state_dir: state_dir,
+            storage: storage,
+            torrent_add_command: self.torrent_add_command.unwrap_or(defaults.torrent_add_command),
+            torrent_add_args: self.torrent_add_args.unwrap_or(defaults.torrent_add_args),
             torrent_file_cache_dir: torrent_file_cache_dir,
+            redact_keys: self.redact_keys.unwrap_or_default(),
         })
     }
 }
 
-impl RTConfig {
-    pub fn from_file(mut f: File) -> Result<RTConfig, Box<Error>> {
-        let mut contents = String::new();
-        f.read_to_string(&mut contents)?;
+fn merge_value<T>(value: &mut Option<T>, other: Option<T>, key: &str, origin: &str,
+                  origins: &mut BTreeMap<String, String>) {
+    if other.is_some() {
+        *value = other;
+        origins.insert(key.to_string(), origin.to_string());
+    }
+}
+
+#[derive(Deserialize)]
+struct EnvList {
+    list: Vec<String>,
+}
 
-        Ok(toml::from_str::<RTConfigValues>(&contents)?.to_config()?)
+fn parse_env_list(value: &str) -> Result<Vec<String>, Box<Error>> {
+    if value.trim_start().starts_with('[') {
+        let parsed: EnvList = toml::from_str(&format!("list = {}", value))?;
+        return Ok(parsed.list);
     }
 
-    pub fn new(config_arg: Option<String>) -> RTConfig {
-        // first check if arg was passed
-        // else, check $RSS_TORRENT_CONFIG
-        // else, check $HOME/.config/rss_torrent.toml
-        //      this should actually be $XDG_CONFIG_DIR or something
-        // else, check /usr/local/etc/rss_torrent.toml
-        // else, check /etc/rss_torrent.toml
-        // there probably is a crate for this (maybe config-rs does this? I couldn't tell)
-        
+    Ok(value.split_whitespace().map(|v| v.to_string()).collect())
+}
+
+/// `name` is only used in the error messages, e.g. "Data".
+fn check_dir(dir: &Path, name: &str) -> Result<(), io::Error> {
+    if !dir.exists() {
+        return Err(io::Error::new(ErrorKind::NotFound,
+                                  format!("{} directory not found: {}", name, dir.to_string_lossy())));
+    }
+    if !dir.is_dir() {
+        return Err(io::Error::new(ErrorKind::InvalidData,
+                                  format!("{} directory is not a directory: {}", name,
+                                          dir.to_string_lossy())));
+    }
 
+    Ok(())
+}
+
+/// The XDG data dir, unless there is only data in the old default location.
+fn default_data_dir() -> Result<PathBuf, io::Error> {
+    let legacy = PathBuf::from(LEGACY_DATA_DIR);
+
+    let xdg = match xdg_data_dir() {
+        Some(dir) => dir,
+        None => {
+            debug!("No home directory found, using {}", LEGACY_DATA_DIR);
+            check_dir(&legacy, "Data")?;
+            return Ok(legacy);
+        }
+    };
+
+    if !xdg.exists() && legacy.is_dir() && fs::read_dir(&legacy)?.next().is_some() {
+        warn!("Using data directory {}, which is deprecated. Move its contents to {} or set \
+               data_dir in the config file.", LEGACY_DATA_DIR, xdg.to_string_lossy());
+        return Ok(legacy);
+    }
+
+    create_default_dir(&xdg)?;
+    Ok(xdg)
+}
+
+/// The XDG state dir, unless the history is still in the data dir where it used to be kept.
+fn default_state_dir(data_dir: &Path) -> Result<PathBuf, io::Error> {
+    let xdg = match xdg_state_dir() {
+        Some(dir) => dir,
+        None => {
+            debug!("No home directory found, keeping state in the data directory");
+            return Ok(data_dir.to_path_buf());
+        }
+    };
+
+    let mut legacy_history = data_dir.to_path_buf();
+    legacy_history.push("history.csv");
+
+    if !xdg.exists() && legacy_history.exists() {
+        warn!("Keeping the history in the data directory {}, which is deprecated. Move \
+               history.csv to {} or set state_dir in the config file.",
+              data_dir.to_string_lossy(), xdg.to_string_lossy());
+        return Ok(data_dir.to_path_buf());
+    }
+
+    create_default_dir(&xdg)?;
+    Ok(xdg)
+}
+
+fn create_default_dir(dir: &Path) -> Result<(), io::Error> {
+    if !dir.exists() {
+        info!("Creating directory {}", dir.to_string_lossy());
+        fs::create_dir_all(dir)?;
+    }
+
+    check_dir(dir, "Default")
+}
+
+impl RTConfig {
+    /// The values as they would be written in a config file.
+    pub fn to_values(&self) -> RTConfigValues {
+        RTConfigValues {
+            data_dir: Some(self.data_dir.to_string_lossy().into_owned()),
+            state_dir: Some(self.state_dir.to_string_lossy().into_owned()),
+            storage: Some(self.storage.to_string()),
+            torrent_add_command: Some(self.torrent_add_command.clone()),
+            torrent_add_args: Some(self.torrent_add_args.clone()),
+            torrent_file_cache_dir: self.torrent_file_cache_dir.as_ref()
+                .map(|d| d.to_string_lossy().into_owned()),
+            redact_keys: Some(self.redact_keys.clone()),
+        }
+    }
+
+    /// Read and merge every config layer. The report says where each value came from.
+    pub fn load(config_arg: Option<String>) -> (RTConfig, ConfigReport) {
+        // Layers, each overriding the ones before it:
+        // defaults
+        // /etc/rss_torrent.toml
+        // /usr/local/etc/rss_torrent.toml
+        // $XDG_CONFIG_HOME/rss-torrent/config.toml, or the deprecated $HOME/.config/rss_torrent.toml
+        // $RSS_TORRENT_CONFIG
+        // the -c argument
+        // $RSS_TORRENT_<KEY> for individual keys
+
+        // the files are paired with whether they were given explicitly, since those have to be
+        // valid while the default locations are skipped if they aren't
         let mut conf_files = Vec::new();
-        if let Some(config_path) = config_arg {
-            conf_files.push(PathBuf::from(config_path));
+        conf_files.push((PathBuf::from(ETC_CONFIG_PATH), false));
+        conf_files.push((PathBuf::from(USR_LOCAL_CONFIG_PATH), false));
+
+        let xdg_file = xdg_config_path();
+        let legacy_home_config = home_dir().map(|mut home_dir| {
+            home_dir.push(LEGACY_HOME_CONFIG_RELPATH);
+            home_dir
+        });
+        match (xdg_file, legacy_home_config) {
+            (Some(ref xdg), Some(ref legacy)) if !xdg.exists() && legacy.exists() => {
+                warn!("Config file {} is deprecated, move it to {}.", legacy.to_string_lossy(),
+                      xdg.to_string_lossy());
+                conf_files.push((legacy.clone(), false));
+            },
+            (Some(xdg), _) => conf_files.push((xdg, false)),
+            (None, _) => trace!("No home directory found"),
         }
 
         let config_var = env::var(CONFIG_ENV_VAR);
         if let Ok(var_file) = config_var {
-            conf_files.push(PathBuf::from(var_file));
+            conf_files.push((PathBuf::from(var_file), true));
         }
         else {
             trace!("No config env var found");
         }
 
-        let home = env::home_dir();
-        if let Some(home_dir) = home {
-            let mut f = home_dir;
-            f.push(HOME_CONFIG_RELPATH);
-            conf_files.push(PathBuf::from(f));
-        }
-        else {
-            trace!("No home directory found")
+        if let Some(config_path) = config_arg {
+            conf_files.push((PathBuf::from(config_path), true));
         }
 
-        conf_files.push(PathBuf::from(USR_LOCAL_CONFIG_PATH));
-        conf_files.push(PathBuf::from(ETC_CONFIG_PATH));
-   
-        for path in conf_files {
+        let mut report = ConfigReport::default();
+        let mut problems = Vec::new();
+        let mut values = RTConfigValues::default();
+        for (path, explicit) in conf_files {
             let f = File::open(&path);
             match f {
                 Ok(file) => {
-                    match RTConfig::from_file(file) {
-                        Ok(config) => {
+                    match RTConfigValues::from_file(file) {
+                        Ok(file_values) => {
                             info!("Found valid config file: {}", path.to_string_lossy());
-                            return config;
+                            values.merge(file_values, &path.to_string_lossy(), &mut report.origins);
+                            report.sources.push(path);
                         },
                         Err(err) => {
                             warn!("Invalid config file {}: {}", path.to_string_lossy(), err);
+                            if explicit {
+                                problems.push(format!("config file {} is invalid: {}",
+                                                      path.to_string_lossy(), err));
+                            }
+                            report.skipped.push((path, format!("invalid: {}", err)));
                         }
                     }
                 },
                 Err(err) => {
-                    debug!("Error trying to open config file {}: {}", path.to_string_lossy(), err);
+                    if explicit {
+                        warn!("Could not open config file {}: {}", path.to_string_lossy(), err);
+                        problems.push(format!("could not open config file {}: {}",
+                                              path.to_string_lossy(), err));
+                    }
+                    else {
+                        debug!("Error trying to open config file {}: {}", path.to_string_lossy(),
+                               err);
+                    }
+                    report.skipped.push((path, format!("could not open: {}", err)));
+                }
+            }
+        }
+
+        if report.sources.is_empty() {
+            warn!("No config files found, using default values");
+        }
+
+        for (var, result) in RTConfigValues::from_env() {
+            match result {
+                Ok(env_values) => {
+                    values.merge(env_values, &format!("${}", var), &mut report.origins);
+                },
+                Err(err) => {
+                    warn!("Invalid ${}: {}", var, err);
+                    problems.push(format!("${} is invalid: {}", var, err));
+                    report.skipped_vars.push((var, err));
                 }
             }
         }
 
-        // if none work, use default values
-        warn!("No config files found, using default values");
-        return Default::default();
+        // commands other than `config` refuse to run with an invalid config, the defaults are
+        // only there so that `config show` has something to show
+        let result = values.to_config();
+        if let Err(ref err) = result {
+            warn!("Invalid config: {}.", err);
+            problems.push(err.to_string());
+        }
+        if !problems.is_empty() {
+            report.invalid = Some(problems.join("; "));
+        }
+
+        match result {
+            Ok(config) => (config, report),
+            Err(_) => {
+                report.origins.clear();
+                report.using_defaults = true;
+
+                match RTConfigValues::default().to_config() {
+                    Ok(config) => (config, report),
+                    Err(err) => {
+                        error!("Could not set up default directories: {}", err);
+                        (Default::default(), report)
+                    }
+                }
+            }
+        }
     }
+}
+
+/// `$XDG_CONFIG_HOME/rss-torrent/config.toml`, or `~/.config/rss-torrent/config.toml` if it isn't
+/// set.
+pub fn xdg_config_path() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|mut dir| {
+        dir.push(XDG_CONFIG_RELPATH);
+        dir
+    })
+}
+
+/// `$XDG_DATA_HOME/rss-torrent`, or `~/.local/share/rss-torrent` if it isn't set.
+pub fn xdg_data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share").map(|mut dir| {
+        dir.push(XDG_DATA_RELPATH);
+        dir
+    })
+}
+
+/// `$XDG_STATE_HOME/rss-torrent`, or `~/.local/state/rss-torrent` if it isn't set.
+pub fn xdg_state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state").map(|mut dir| {
+        dir.push(XDG_STATE_RELPATH);
+        dir
+    })
+}
+
+/// The spec says relative paths in the variables are invalid and should be ignored.
+fn xdg_dir(var: &str, home_relpath: &str) -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(var).map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Some(dir);
+        }
+        debug!("Ignoring relative ${}", var);
+    }
+
+    home_dir().map(|mut home| {
+        home.push(home_relpath);
+        home
+    })
+}
 
+/// `$HOME`, or None if it isn't set or is empty. Unlike the deprecated `std::env::home_dir` this
+/// doesn't fall back to the password database, so without `$HOME` only the directories given
+/// in the config or the `XDG_*` variables are used.
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").filter(|home| !home.is_empty()).map(PathBuf::from)
 }
@@ -0,0 +1,157 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use toml;
+
+use commands::{RTConfigCheck, RTConfigCmd, RTConfigCommand, RTConfigInit, RTConfigShow};
+use config::{self, ConfigReport, RTConfig, RTConfigValues};
+
+const CONFIG_TEMPLATE: &str = r#"# rss-torrent configuration.
+
+# Directory where the feeds and aliases are stored. If it is set, it must already exist.
+data_dir = "_DATA_DIR"
+
+# Directory where the history and log are kept. Defaults to $XDG_STATE_HOME/rss-torrent.
+#state_dir = "/var/lib/rss-torrent"
+
+# How the databases are stored, either "csv" or "sqlite". See `rss-torrent db migrate`.
+#storage = "csv"
+
+# Command that is run for every new item. `_TORRENT_PATH` in the arguments is replaced with the
+# path of the downloaded torrent file, or with the magnet link.
+torrent_add_command = "transmission-remote"
+torrent_add_args = ["-a", "_TORRENT_PATH", "-sr", "50"]
+
+# Directory to keep downloaded torrent files in. Without it they go in the system temp directory.
+#torrent_file_cache_dir = "/var/cache/rss-torrent"
+
+# Url query keys to mask in log output, in addition to the usual passkey, apikey, etc.
+#redact_keys = ["sid"]
+"#;
+
+/// Returns false if the command failed, e.g. if the checked file is invalid.
+pub fn run_config(cmd: &RTConfigCmd, config: &RTConfig, report: &ConfigReport) -> bool {
+    match cmd.cmd {
+        RTConfigCommand::Show(ref show) => {
+            show_config(show, config, report);
+            true
+        },
+        RTConfigCommand::Check(ref check) => check_config(check),
+        RTConfigCommand::Init(ref init) => init_config(init),
+    }
+}
+
+fn show_config(cmd: &RTConfigShow, config: &RTConfig, report: &ConfigReport) {
+    if report.sources.is_empty() {
+        println!("# No config file found, using default values");
+    }
+    else {
+        println!("# Merged config files, later ones take precedence:");
+    }
+    for path in &report.sources {
+        println!("#   {}", path.to_string_lossy());
+    }
+
+    for &(ref path, ref reason) in &report.skipped {
+        println!("# Skipped {}: {}", path.to_string_lossy(), reason);
+    }
+    for &(ref var, ref reason) in &report.skipped_vars {
+        println!("# Skipped ${}: {}", var, reason);
+    }
+    if let Some(ref err) = report.invalid {
+        println!("# Invalid config: {}. Other commands refuse to run until it is fixed.", err);
+    }
+    if report.using_defaults {
+        println!("# Showing the default values instead.");
+    }
+
+    let values = match toml::to_string(&config.to_values()) {
+        Ok(values) => values,
+        Err(err) => {
+            error!("Could not print config: {}", err);
+            return;
+        }
+    };
+
+    for line in values.lines() {
+        // every value is a single `key = value` line
+        match line.find(" = ") {
+            Some(i) if cmd.origin => println!("{}  # from {}", line, report.origin(&line[..i])),
+            _ => println!("{}", line),
+        }
+    }
+}
+
+fn check_config(cmd: &RTConfigCheck) -> bool {
+    let path = Path::new(&cmd.file);
+
+    let mut contents = String::new();
+    if let Err(err) = File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        println!("{}: could not read file: {}", path.to_string_lossy(), err);
+        return false;
+    }
+
+    let values: RTConfigValues = match toml::from_str(&contents) {
+        Ok(values) => values,
+        Err(err) => {
+            // line_col is 0-indexed
+            match err.line_col() {
+                Some((line, col)) => println!("{}:{}:{}: {}", path.to_string_lossy(), line + 1,
+                                              col + 1, err),
+                None => println!("{}: {}", path.to_string_lossy(), err),
+            }
+            return false;
+        }
+    };
+
+    match values.to_config() {
+        Ok(_) => {
+            println!("{}: ok", path.to_string_lossy());
+            true
+        },
+        Err(err) => {
+            println!("{}: {}", path.to_string_lossy(), err);
+            false
+        }
+    }
+}
+
+fn init_config(cmd: &RTConfigInit) -> bool {
+    match write_template(cmd) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Could not write config file: {}", err);
+            false
+        }
+    }
+}
+
+/// Write the template to the XDG config location, creating the data directory it points to so
+/// that the new config is valid right away.
+fn write_template(cmd: &RTConfigInit) -> Result<(), Box<Error>> {
+    let config_path = config::xdg_config_path().ok_or("Could not find the home directory")?;
+    let data_dir = config::xdg_data_dir().ok_or("Could not find the home directory")?;
+
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
+    fs::create_dir_all(&data_dir)?;
+
+    // create_new so an existing config is never overwritten by accident
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(cmd.force)
+        .create_new(!cmd.force)
+        .open(&config_path)
+        .map_err(|e| format!("{}: {}{}", config_path.to_string_lossy(), e,
+                             if cmd.force { "" } else { " (use --force to overwrite it)" }))?;
+
+    let template = CONFIG_TEMPLATE.replace("_DATA_DIR", &data_dir.to_string_lossy());
+    file.write_all(template.as_bytes())?;
+
+    println!("Wrote {}", config_path.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+
+use alias::{Alias, Aliases};
+use alias_util;
+use feed_util::{self, Feed, Feeds};
+use history_util::{self, HistoryEntry};
+use memory_store::MemoryStore;
+use run_util;
+use store::Store;
+
+/// The original storage: feeds.csv and aliases.csv in the data directory, and history.csv and
+/// last_run.txt for the time of the last update in the state directory. Since the csv files can
+/// only be rewritten as a whole, every change reads and rewrites the entire file.
+pub struct CsvStore {
+    data_dir: PathBuf,
+    state_dir: PathBuf,
+}
+
+impl CsvStore {
+    pub fn new(data_dir: &Path, state_dir: &Path) -> CsvStore {
+        CsvStore {
+            data_dir: data_dir.to_path_buf(),
+            state_dir: state_dir.to_path_buf(),
+        }
+    }
+
+    /// Whether any of the files exist, without creating them.
+    pub fn exists(data_dir: &Path, state_dir: &Path) -> bool {
+        data_dir.join(feed_util::FEED_DB_FILENAME).exists()
+            || data_dir.join(alias_util::ALIAS_DB_FILENAME).exists()
+            || state_dir.join(history_util::HISTORY_DB_FILENAME).exists()
+            || state_dir.join(run_util::LAST_RUN_FILENAME).exists()
+    }
+}
+
+impl Store for CsvStore {
+    fn list_feeds(&mut self) -> Result<Feeds, Box<Error>> {
+        feed_util::read_feed_db(&self.data_dir)
+    }
+
+    fn insert_feed(&mut self, feed: Feed) -> Result<bool, Box<Error>> {
+        let mut feeds = self.list_feeds()?;
+        if !feeds.insert(feed) {
+            return Ok(false);
+        }
+
+        feed_util::write_feed_db(&self.data_dir, feeds)?;
+        Ok(true)
+    }
+
+    fn remove_feed(&mut self, feed: &Feed) -> Result<bool, Box<Error>> {
+        let mut feeds = self.list_feeds()?;
+        if !feeds.remove(feed) {
+            return Ok(false);
+        }
+
+        feed_util::write_feed_db(&self.data_dir, feeds)?;
+        Ok(true)
+    }
+
+    fn update_feed(&mut self, old: &Feed, new: Feed) -> Result<bool, Box<Error>> {
+        let mut memory = MemoryStore::default();
+        memory.feeds = self.list_feeds()?;
+        if !memory.update_feed(old, new)? {
+            return Ok(false);
+        }
+
+        feed_util::write_feed_db(&self.data_dir, memory.feeds)?;
+        Ok(true)
+    }
+
+    fn list_aliases(&mut self) -> Result<Aliases, Box<Error>> {
+        alias_util::read_alias_db(&self.data_dir)
+    }
+
+    fn insert_alias(&mut self, alias: Alias) -> Result<Option<Alias>, Box<Error>> {
+        let mut aliases = self.list_aliases()?;
+        let old = aliases.insert(alias.name.clone(), alias);
+
+        alias_util::write_alias_db(&self.data_dir, aliases)?;
+        Ok(old)
+    }
+
+    fn remove_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+        let mut aliases = self.list_aliases()?;
+        let old = aliases.remove(name);
+
+        if old.is_some() {
+            alias_util::write_alias_db(&self.data_dir, aliases)?;
+        }
+        Ok(old)
+    }
+
+    fn list_history(&mut self) -> Result<Vec<HistoryEntry>, Box<Error>> {
+        history_util::read_history_db(&self.state_dir)
+    }
+
+    fn record_history(&mut self, entries: &[HistoryEntry]) -> Result<(), Box<Error>> {
+        history_util::append_history_db(&self.state_dir, entries)
+    }
+
+    fn last_run(&mut self) -> Result<Option<DateTime<FixedOffset>>, Box<Error>> {
+        run_util::read_last_run(&self.state_dir)
+    }
+
+    fn record_run(&mut self, finished: DateTime<FixedOffset>) -> Result<(), Box<Error>> {
+        run_util::write_last_run(&self.state_dir, finished)
+    }
+
+    fn backup(&mut self, tag: &str) -> Result<Vec<PathBuf>, Box<Error>> {
+        let feeds = feed_util::backup_feed_db(&self.data_dir, tag)?;
+        let aliases = alias_util::backup_alias_db(&self.data_dir, tag)?;
+
+        Ok(feeds.into_iter().chain(aliases).collect())
+    }
+
+    /// Runs `f` against an in-memory copy and only writes the files if it succeeds.
+    fn transaction(&mut self, f: &mut FnMut(&mut Store) -> Result<(), Box<Error>>)
+        -> Result<(), Box<Error>> {
+        let mut memory = MemoryStore::load(self)?;
+        let last_run = memory.last_run;
+        f(&mut memory)?;
+
+        if let Some(finished) = memory.last_run {
+            if memory.last_run != last_run {
+                run_util::write_last_run(&self.state_dir, finished)?;
+            }
+        }
+
+        history_util::append_history_db(&self.state_dir, memory.new_history())?;
+        alias_util::write_alias_db(&self.data_dir, memory.aliases)?;
+        feed_util::write_feed_db(&self.data_dir, memory.feeds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    #[test]
+    fn reading_creates_no_files() {
+        let dir = TempDir::new("csv_store").unwrap();
+        let (data_dir, state_dir) = (dir.path().join("data"), dir.path().join("state"));
+        fs::create_dir(&data_dir).unwrap();
+        fs::create_dir(&state_dir).unwrap();
+
+        // what dry runs do before working on the copy
+        let memory = MemoryStore::load(&mut CsvStore::new(&data_dir, &state_dir)).unwrap();
+        assert!(memory.feeds.is_empty());
+        assert!(memory.aliases.is_empty());
+        assert!(memory.history.is_empty());
+        assert_eq!(memory.last_run, None);
+
+        assert_eq!(fs::read_dir(&data_dir).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&state_dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+
+use chrono::{DateTime, FixedOffset};
+
+use alias::Aliases;
+use commands::{RTDb, RTDbCommand, RTDbMigrate};
+use config::RTConfig;
+use feed_util::Feeds;
+use history_util::HistoryEntry;
+use store::{self, Backend, Store};
+
+pub fn run_db(cmd: RTDb, config: &RTConfig) {
+    match cmd.cmd {
+        RTDbCommand::Migrate(migrate) => migrate_db(migrate, config),
+    }
+}
+
+fn migrate_db(cmd: RTDbMigrate, config: &RTConfig) {
+    let from = match cmd.to {
+        Backend::Csv => Backend::Sqlite,
+        Backend::Sqlite => Backend::Csv,
+    };
+
+    info!("Migrating databases from {} to {}.", from, cmd.to);
+
+    // opening the source would create an empty one, which would then be migrated successfully
+    if !store::backend_exists(from, config) {
+        error!("There is no {} database in {}. Not migrating.", from,
+               config.data_dir.to_string_lossy());
+        return;
+    }
+
+    let result = store::open_backend(from, config)
+        .and_then(|source| store::open_backend(cmd.to, config).map(|target| (source, target)));
+
+    let (mut source, mut target) = match result {
+        Ok(stores) => stores,
+        Err(err) => {
+            error!("Could not open databases: {}. Not migrating.", err);
+            return;
+        }
+    };
+
+    match copy_store(&mut *source, &mut *target) {
+        Ok(()) => {
+            info!("Sucessfully migrated databases.");
+            println!("Migrated to {}. Set `storage = \"{}\"` in your config file to use it.",
+                     cmd.to, cmd.to);
+        },
+        Err(err) => error!("Could not migrate databases: {}", err),
+    }
+}
+
+/// Copy everything from `source` into `target`, which must be empty so we don't silently merge or
+/// overwrite anything. The copy is read back and compared before the transaction is committed, so
+/// a lossy conversion leaves the target as it was.
+fn copy_store(source: &mut Store, target: &mut Store) -> Result<(), Box<Error>> {
+    let feeds = source.list_feeds()?;
+    let aliases = source.list_aliases()?;
+    let history = source.list_history()?;
+    let last_run = source.last_run()?;
+
+    if !target.list_feeds()?.is_empty() || !target.list_aliases()?.is_empty()
+        || !target.list_history()?.is_empty() {
+        return Err(Box::new(io::Error::new(ErrorKind::AlreadyExists,
+                                           "Target database is not empty")));
+    }
+
+    info!("Copying {} feeds, {} aliases and {} history entries.",
+          feeds.len(), aliases.len(), history.len());
+
+    target.transaction(&mut |tx| {
+        for feed in &feeds {
+            tx.insert_feed(feed.clone())?;
+        }
+        for alias in aliases.values() {
+            tx.insert_alias(alias.clone())?;
+        }
+        if let Some(finished) = last_run {
+            tx.record_run(finished)?;
+        }
+        tx.record_history(&history)?;
+
+        check_copy(tx, &feeds, &aliases, &history, last_run)
+    })?;
+
+    // the csv store only writes its files when the transaction is committed, so check what
+    // actually ended up on disk too
+    check_copy(target, &feeds, &aliases, &history, last_run)
+}
+
+/// Check that `target` has exactly the given contents, i.e. that the conversion was lossless.
+fn check_copy(target: &mut Store, feeds: &Feeds, aliases: &Aliases, history: &[HistoryEntry],
+              last_run: Option<DateTime<FixedOffset>>) -> Result<(), Box<Error>> {
+    let copied_feeds = target.list_feeds()?;
+    let copied_aliases = target.list_aliases()?;
+    let copied_history = target.list_history()?;
+
+    let aliases_match = copied_aliases.len() == aliases.len() && aliases.iter()
+        .all(|(name, alias)| copied_aliases.get(name).map_or(false, |a| a.url == alias.url));
+
+    let feeds_match = copied_feeds.len() == feeds.len() && feeds.iter()
+        .all(|feed| copied_feeds.get(feed).map_or(false, |f| {
+            f.id == feed.id && f.last_update == feed.last_update && f.filters == feed.filters
+                && f.paused == feed.paused && f.failures == feed.failures
+                && f.last_error == feed.last_error && f.last_match == feed.last_match
+                && f.alias == feed.alias && f.added == feed.added
+                && f.last_fetch == feed.last_fetch
+        }));
+
+    if !feeds_match || !aliases_match || copied_history.as_slice() != history
+        || target.last_run()? != last_run {
+        return Err(Box::new(io::Error::new(ErrorKind::InvalidData,
+                                           "Migrated data does not match the original")));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use reqwest::Url;
+    use tempdir::TempDir;
+
+    use alias::Alias;
+    use csv_store::CsvStore;
+    use feed_util::Feed;
+    use memory_store::MemoryStore;
+    use sqlite_store::SqliteStore;
+
+    fn date(d: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(d).unwrap()
+    }
+
+    fn fill(store: &mut Store) {
+        let url = Url::parse("https://t.example/rss?q=show").unwrap();
+        let mut feed = Feed::new(url.clone(), vec!["720p".to_string()], &Feeds::new());
+        feed.last_update = Some(date("2018-10-15T10:00:00+02:00"));
+        feed.last_error = Some("timed out, \"twice\"".to_string());
+        feed.alias = Some("tracker:q=show".to_string());
+        store.insert_feed(feed).unwrap();
+
+        let url = Url::parse("https://t.example/rss?q={q}").unwrap();
+        store.insert_alias(Alias { name: "tracker".to_string(), url: url }).unwrap();
+
+        store.record_history(&[HistoryEntry {
+            feed_url: "https://t.example/rss?q=show".to_string(),
+            title: "Some Show 720p".to_string(),
+            guid: Some("1".to_string()),
+            info_hash: None,
+            dispatched: date("2018-10-15T11:00:00Z"),
+        }]).unwrap();
+        store.record_run(date("2018-10-15T11:00:05Z")).unwrap();
+    }
+
+    /// Forgets the titles of history entries, like a backend with a broken column would.
+    #[derive(Default)]
+    struct LossyStore(MemoryStore);
+
+    impl Store for LossyStore {
+        fn list_feeds(&mut self) -> Result<Feeds, Box<Error>> { self.0.list_feeds() }
+        fn insert_feed(&mut self, feed: Feed) -> Result<bool, Box<Error>> {
+            self.0.insert_feed(feed)
+        }
+        fn remove_feed(&mut self, feed: &Feed) -> Result<bool, Box<Error>> {
+            self.0.remove_feed(feed)
+        }
+        fn update_feed(&mut self, old: &Feed, new: Feed) -> Result<bool, Box<Error>> {
+            self.0.update_feed(old, new)
+        }
+        fn list_aliases(&mut self) -> Result<Aliases, Box<Error>> { self.0.list_aliases() }
+        fn insert_alias(&mut self, alias: Alias) -> Result<Option<Alias>, Box<Error>> {
+            self.0.insert_alias(alias)
+        }
+        fn remove_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+            self.0.remove_alias(name)
+        }
+        fn list_history(&mut self) -> Result<Vec<HistoryEntry>, Box<Error>> {
+            self.0.list_history()
+        }
+        fn record_history(&mut self, entries: &[HistoryEntry]) -> Result<(), Box<Error>> {
+            let entries: Vec<HistoryEntry> = entries.iter()
+                .map(|e| HistoryEntry { title: String::new(), ..e.clone() })
+                .collect();
+            self.0.record_history(&entries)
+        }
+        fn last_run(&mut self) -> Result<Option<DateTime<FixedOffset>>, Box<Error>> {
+            self.0.last_run()
+        }
+        fn record_run(&mut self, finished: DateTime<FixedOffset>) -> Result<(), Box<Error>> {
+            self.0.record_run(finished)
+        }
+        fn backup(&mut self, tag: &str) -> Result<Vec<PathBuf>, Box<Error>> { self.0.backup(tag) }
+        fn transaction(&mut self, f: &mut FnMut(&mut Store) -> Result<(), Box<Error>>)
+            -> Result<(), Box<Error>> {
+            let snapshot = self.0.clone();
+            let result = f(self);
+            if result.is_err() {
+                self.0 = snapshot;
+            }
+            result
+        }
+    }
+
+    #[test]
+    fn copies_everything_between_backends() {
+        let dir = TempDir::new("db").unwrap();
+        let mut csv = CsvStore::new(dir.path(), dir.path());
+        fill(&mut csv);
+
+        let mut sqlite = SqliteStore::open(dir.path()).unwrap();
+        copy_store(&mut csv, &mut sqlite).unwrap();
+
+        let expected = MemoryStore::load(&mut csv).unwrap();
+        assert_eq!(expected.history.len(), 1);
+        check_copy(&mut sqlite, &expected.feeds, &expected.aliases, &expected.history,
+                   expected.last_run).unwrap();
+    }
+
+    #[test]
+    fn lossy_copies_are_rolled_back() {
+        let mut source = MemoryStore::default();
+        fill(&mut source);
+
+        let mut target = LossyStore::default();
+        assert!(copy_store(&mut source, &mut target).is_err());
+
+        assert!(target.list_feeds().unwrap().is_empty());
+        assert!(target.list_aliases().unwrap().is_empty());
+        assert!(target.list_history().unwrap().is_empty());
+        assert_eq!(target.last_run().unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_targets_that_are_not_empty() {
+        let mut source = MemoryStore::default();
+        fill(&mut source);
+
+        let mut target = MemoryStore::default();
+        let url = Url::parse("https://other.example/rss").unwrap();
+        target.insert_feed(Feed::new(url, Vec::new(), &Feeds::new())).unwrap();
+
+        assert!(copy_store(&mut source, &mut target).is_err());
+        assert_eq!(target.list_feeds().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn only_finds_backends_with_data() {
+        let dir = TempDir::new("db").unwrap();
+        let config = RTConfig {
+            data_dir: dir.path().join("data"),
+            state_dir: dir.path().join("state"),
+            ..RTConfig::default()
+        };
+        fs::create_dir(&config.data_dir).unwrap();
+        fs::create_dir(&config.state_dir).unwrap();
+
+        assert!(!store::backend_exists(Backend::Csv, &config));
+        assert!(!store::backend_exists(Backend::Sqlite, &config));
+
+        store::open_backend(Backend::Sqlite, &config).unwrap();
+        assert!(store::backend_exists(Backend::Sqlite, &config));
+        assert!(!store::backend_exists(Backend::Csv, &config));
+
+        fill(&mut *store::open_backend(Backend::Csv, &config).unwrap());
+        assert!(store::backend_exists(Backend::Csv, &config));
+    }
+}
@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+// Shared bits of feed_util, alias_util and history_util.
+
+/// Path of the db file `filename` in the data or state dir `dir`. `name` is only used for logging,
+/// e.g. "Feeds".
+pub fn db_path(dir: &Path, filename: &str, name: &str) -> PathBuf {
+    trace!("Opening {} file.", name.to_lowercase());
+
+    let mut db_path = dir.to_path_buf();
+    db_path.push(filename);
+
+    if !db_path.exists() {
+        warn!("{} db not found at {}, creating.", name, db_path.to_string_lossy());
+    }
+    else {
+        trace!("{} db found at {}.", name, db_path.to_string_lossy());
+    }
+
+    db_path
+}
+
+/// Open the db file `filename` in `dir` for reading, or return `None` if it does not exist. Unlike
+/// the writes, reading never creates the file, so dry runs leave the data and state dirs alone.
+pub fn open_existing_db(dir: &Path, filename: &str, name: &str) -> Result<Option<File>, Box<Error>> {
+    let mut db_path = dir.to_path_buf();
+    db_path.push(filename);
+
+    match File::open(&db_path) {
+        Ok(file) => Ok(Some(file)),
+        Err(ref err) if err.kind() == ErrorKind::NotFound => {
+            debug!("{} db not found at {}.", name, db_path.to_string_lossy());
+            Ok(None)
+        },
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+/// Replace the contents of `db_file` with `buf`.
+pub fn overwrite_db(db_file: &mut File, buf: &[u8]) -> Result<(), Box<Error>> {
+    // there should be a better way of doing this
+    let result = db_file.write_all(buf).map_err(|e| {
+        error!("Error writing to file. DATA MAY BE CORRUPTED!");
+        e
+    })
+    .and_then(|_| {
+        db_file.set_len(buf.len() as u64)
+    });
+
+    Ok(result?)
+}
+
+/// Copy `path` to `<path>.<tag>.bak`, or to `<path>.<tag>.<timestamp>.bak` if that already exists,
+/// and return where it went. Returns `None` if `path` doesn't exist.
+pub fn backup_file(path: &Path, tag: &str) -> Result<Option<PathBuf>, Box<Error>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let filename = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let mut backup_path = path.to_path_buf();
+    backup_path.set_file_name(format!("{}.{}.bak", filename, tag));
+    if backup_path.exists() {
+        backup_path.set_file_name(format!("{}.{}.{}.bak", filename, tag, Local::now().timestamp()));
+    }
+
+    fs::copy(path, &backup_path)?;
+    Ok(Some(backup_path))
+}
@@ -1,6 +1,35 @@
 use commands::RTDelete;
-use config::RTConfig;
 
-pub fn delete_feed(delete: RTDelete, config: &RTConfig) {
-    unimplemented!();
+use resolve::select_feeds;
+use store::Store;
+
+pub fn delete_feed(cmd: RTDelete, store: &mut Store) {
+    let to_delete = match select_feeds(&cmd.url_or_alias, cmd.search_all, &cmd.filters,
+                                       "deleting", store) {
+        Some(feeds) => feeds,
+        None => return,
+    };
+
+    if cmd.dry_run {
+        for feed in &to_delete {
+            println!("Would delete {}", feed);
+        }
+        return;
+    }
+
+    let result = store.transaction(&mut |tx| {
+        for feed in &to_delete {
+            tx.remove_feed(feed)?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            for feed in &to_delete {
+                info!("Deleted feed {}", feed);
+            }
+        },
+        Err(err) => error!("Could not delete feeds: {}", err),
+    }
 }
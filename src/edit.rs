@@ -0,0 +1,285 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+
+use commands::RTEdit;
+
+use alias::AliasRef;
+use feed_util::{self, Feed};
+use redact::{self, RedactedUrl};
+use resolve;
+use store::Store;
+
+fn edit_error(message: String) -> Box<Error> {
+    Box::new(io::Error::new(ErrorKind::InvalidInput, message))
+}
+
+/// Whether two filters are the same after normalizing them.
+fn same_filter(a: &str, b: &str) -> bool {
+    feed_util::normalize_filters(&[a.to_string()]) == feed_util::normalize_filters(&[b.to_string()])
+}
+
+pub fn edit_feed(cmd: RTEdit, store: &mut Store) {
+    let result = edited_feed(&cmd, store);
+
+    let (old, new) = match result {
+        Ok(feeds) => feeds,
+        Err(err) => {
+            error!("Not editing feed: {}", err);
+            return;
+        }
+    };
+
+    let changes = diff(&old, &new);
+    if changes.is_empty() {
+        warn!("Nothing to change for feed {}.", old);
+        return;
+    }
+
+    if cmd.dry_run {
+        println!("Would change feed [{}]:", old.id);
+        for line in changes {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    let description = new.to_string();
+    match store.transaction(&mut |tx| save_edit(&old, &new, tx)) {
+        Ok(()) => {
+            for line in changes {
+                debug!("{}", line);
+            }
+            info!("Sucessfully edited feed {}.", description);
+        },
+        Err(err) => error!("Could not edit feed: {}", err),
+    }
+}
+
+/// Replace `old` with `new`, which fails if `old` was removed or changed since it was read.
+fn save_edit(old: &Feed, new: &Feed, store: &mut Store) -> Result<(), Box<Error>> {
+    if !store.update_feed(old, new.clone())? {
+        return Err(Box::new(io::Error::new(ErrorKind::NotFound,
+                                           format!("Feed {} no longer exists", old))));
+    }
+    Ok(())
+}
+
+/// The feed to edit and what it becomes. Its id, history and timestamps are kept unless they are
+/// explicitly changed.
+fn edited_feed(cmd: &RTEdit, store: &mut Store) -> Result<(Feed, Feed), Box<Error>> {
+    let target = resolve::resolve_target(store, &cmd.id_or_url)?;
+    let feeds = store.list_feeds()?;
+
+    let mut selected = target.select(&feeds, &[]);
+    selected.sort_by(|a, b| a.id.cmp(&b.id));
+    let old = match selected.len() {
+        0 => return Err(edit_error(format!("No feeds found for {}", cmd.id_or_url))),
+        1 => selected.remove(0),
+        _ => {
+            let ids: Vec<&str> = selected.iter().map(|f| f.id.as_str()).collect();
+            return Err(edit_error(format!("Several feeds have this url, give the id of one of \
+                                           them instead: {}", ids.join(", "))));
+        }
+    };
+
+    let mut new = old.clone();
+
+    if cmd.clear_filters {
+        new.filters.clear();
+    }
+    if !cmd.set_filters.is_empty() {
+        new.filters = cmd.set_filters.clone();
+    }
+
+    for removed in &cmd.remove_filters {
+        let before = new.filters.len();
+        new.filters.retain(|f| !same_filter(f, removed));
+        if new.filters.len() == before {
+            return Err(edit_error(format!("Feed {} has no filter \"{}\"", old, removed)));
+        }
+    }
+
+    for added in &cmd.add_filters {
+        if new.filters.iter().any(|f| same_filter(f, added)) {
+            warn!("Feed {} already has the filter \"{}\".", old, added);
+            continue;
+        }
+        new.filters.push(added.clone());
+    }
+
+    if let Some(ref url_or_alias) = cmd.url {
+        new.url = resolve::resolve_url(store, url_or_alias)?;
+        // the new url doesn't come from the alias the feed followed, if any
+        new.alias = None;
+
+        if cmd.link_alias {
+            // the parameters were already checked by resolve_url
+            let alias_ref = AliasRef::parse(url_or_alias)?;
+            if store.get_alias(&alias_ref.name)?.is_none() {
+                return Err(edit_error(format!("{} is not an alias, so there is nothing to link",
+                                              url_or_alias)));
+            }
+            new.alias = Some(alias_ref.to_string());
+        }
+    }
+
+    if cmd.unlink_alias {
+        new.alias = None;
+    }
+
+    if let Some(since) = cmd.since {
+        new.last_update = Some(since.to_time());
+    }
+
+    if let Some(existing) = feeds.iter().find(|f| f.id != old.id && **f == new) {
+        return Err(edit_error(format!("Feed {} already has the same url and filters", existing)));
+    }
+
+    Ok((old, new))
+}
+
+/// The fields that differ between `old` and `new`, as `-` and `+` lines.
+fn diff(old: &Feed, new: &Feed) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    // compared before redacting, so changing only a secret still shows up
+    if old.url != new.url {
+        lines.push(format!("- url:         {}", RedactedUrl(&old.url)));
+        lines.push(format!("+ url:         {}", RedactedUrl(&new.url)));
+    }
+    if old.alias != new.alias {
+        let describe_alias = |feed: &Feed| {
+            feed.alias.as_ref().map(|a| redact::redact_alias_ref(a)).unwrap_or_default()
+        };
+        lines.push(format!("- alias:       {}", describe_alias(old)));
+        lines.push(format!("+ alias:       {}", describe_alias(new)));
+    }
+
+    let describe = |feed: &Feed| vec![
+        ("filters:    ", feed.filters.join(", ")),
+        ("last update:", feed.last_update.map(|d| d.to_rfc3339())
+                             .unwrap_or_else(|| "never".to_string())),
+    ];
+
+    for ((name, before), (_, after)) in describe(old).into_iter().zip(describe(new)) {
+        if before != after {
+            lines.push(format!("- {} {}", name, before));
+            lines.push(format!("+ {} {}", name, after));
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::Url;
+
+    use alias::Alias;
+    use feed_util::Feeds;
+    use memory_store::MemoryStore;
+    use redact::SECRETS_LOCK;
+
+    fn edit(id: &str) -> RTEdit {
+        RTEdit {
+            id_or_url: id.to_string(),
+            add_filters: Vec::new(),
+            remove_filters: Vec::new(),
+            set_filters: Vec::new(),
+            clear_filters: false,
+            url: None,
+            link_alias: false,
+            unlink_alias: false,
+            since: None,
+            dry_run: true,
+        }
+    }
+
+    /// A store with a single feed that follows the alias `tracker`, and that feed.
+    fn store_with_feed() -> (MemoryStore, Feed) {
+        let mut store = MemoryStore::default();
+        let url = Url::parse("https://t.example/rss?passkey=abc123&q=show").unwrap();
+        store.insert_alias(Alias { name: "tracker".to_string(),
+                                   url: Url::parse("https://t.example/rss?q={q}").unwrap() })
+            .unwrap();
+
+        let mut feed = Feed::new(url, vec!["720p".to_string(), "Some Show".to_string()],
+                                 &Feeds::new());
+        feed.alias = Some("tracker:q=show".to_string());
+        store.insert_feed(feed.clone()).unwrap();
+
+        (store, feed)
+    }
+
+    #[test]
+    fn diffs_changed_fields() {
+        let (mut store, feed) = store_with_feed();
+
+        let mut cmd = edit(&feed.id);
+        cmd.remove_filters = vec!["show  some".to_string()];
+        cmd.add_filters = vec!["1080p".to_string(), "720P".to_string()];
+        cmd.unlink_alias = true;
+
+        let (old, new) = edited_feed(&cmd, &mut store).unwrap();
+        assert_eq!(old, feed);
+        assert_eq!(new.id, feed.id);
+        assert_eq!(diff(&old, &new), vec![
+            "- alias:       tracker:q=show",
+            "+ alias:       ",
+            "- filters:     720p, Some Show",
+            "+ filters:     720p, 1080p",
+        ]);
+
+        // nothing to change
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn diffs_redact_secrets() {
+        let _lock = SECRETS_LOCK.lock().unwrap();
+        let (mut store, feed) = store_with_feed();
+
+        let mut cmd = edit(&feed.id);
+        cmd.url = Some("https://t.example/rss?passkey=def456&q=show".to_string());
+
+        let (old, new) = edited_feed(&cmd, &mut store).unwrap();
+        let lines = diff(&old, &new);
+        assert_eq!(lines, vec![
+            "- url:         https://t.example/rss?passkey=REDACTED&q=show",
+            "+ url:         https://t.example/rss?passkey=REDACTED&q=show",
+            "- alias:       tracker:q=show",
+            "+ alias:       ",
+        ]);
+    }
+
+    #[test]
+    fn dry_runs_change_nothing() {
+        let (mut store, feed) = store_with_feed();
+
+        let mut cmd = edit(&feed.id);
+        cmd.clear_filters = true;
+        edit_feed(cmd, &mut store);
+        assert_eq!(store.get_feed(&feed.id).unwrap().unwrap().filters, feed.filters);
+
+        let mut cmd = edit(&feed.id);
+        cmd.clear_filters = true;
+        cmd.dry_run = false;
+        edit_feed(cmd, &mut store);
+        assert!(store.get_feed(&feed.id).unwrap().unwrap().filters.is_empty());
+    }
+
+    #[test]
+    fn vanished_feeds_are_not_saved() {
+        let (mut store, feed) = store_with_feed();
+
+        let mut cmd = edit(&feed.id);
+        cmd.clear_filters = true;
+        let (old, new) = edited_feed(&cmd, &mut store).unwrap();
+
+        store.remove_feed(&feed).unwrap();
+        assert!(store.transaction(&mut |tx| save_edit(&old, &new, tx)).is_err());
+        assert!(store.list_feeds().unwrap().is_empty());
+    }
+}
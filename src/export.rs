@@ -0,0 +1,34 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use commands::{RTExport, RTExportCommand, RTExportOpml};
+use opml;
+use store::Store;
+
+pub fn run_export(cmd: RTExport, store: &mut Store) {
+    match cmd.cmd {
+        RTExportCommand::Opml(opml_cmd) => {
+            if let Err(err) = export_opml(&opml_cmd, store) {
+                error!("Could not export feeds: {}", err);
+            }
+        },
+    }
+}
+
+fn export_opml(cmd: &RTExportOpml, store: &mut Store) -> Result<(), Box<Error>> {
+    let feeds = store.list_feeds()?;
+    let aliases = store.list_aliases()?;
+
+    let contents = opml::write_opml(&feeds, &aliases)?;
+
+    match cmd.output {
+        Some(ref path) => {
+            File::create(path)?.write_all(contents.as_bytes())?;
+            info!("Exported {} feeds and {} aliases to {}.", feeds.len(), aliases.len(), path);
+        },
+        None => print!("{}", contents),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use std::error::Error;
+use std::io::{self, Read, ErrorKind};
+
+use atom_syndication as atom;
+use rss;
+
+use reqwest::{self, Url};
+
+use item::Item;
+use json_feed;
+use redact::RedactedUrl;
+
+/// The feed formats we know how to read. RSS 0.9x/1.0 (RDF) and 2.0 are all handled by the rss
+/// crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+/// Guess the format of a feed from its root element.
+pub fn detect_format(body: &str) -> Option<FeedFormat> {
+    let mut rest = body.trim_start_matches('\u{feff}');
+
+    if rest.trim_start().starts_with('{') {
+        return Some(FeedFormat::Json);
+    }
+
+    // skip the xml declaration, comments, doctypes and processing instructions
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = if rest.starts_with("<!--") { rest.find("-->").map(|i| i + 3) }
+                      else { rest.find('>').map(|i| i + 1) };
+            match end {
+                Some(i) => rest = &rest[i..],
+                None => return None,
+            }
+        }
+        else {
+            break;
+        }
+    }
+
+    if !rest.starts_with('<') {
+        return None;
+    }
+
+    let name_end = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(rest.len());
+    let name = &rest[1..name_end];
+    // ignore namespace prefixes, e.g. <rdf:RDF> or <atom:feed>
+    let local_name = name.rsplit(':').next().unwrap_or(name);
+
+    match local_name {
+        "rss" | "RDF" => Some(FeedFormat::Rss),
+        "feed" => Some(FeedFormat::Atom),
+        _ => None,
+    }
+}
+
+/// Parse a feed in any of the supported formats into a list of items.
+pub fn parse_feed(body: &str) -> Result<Vec<Item>, Box<Error>> {
+    match detect_format(body) {
+        Some(FeedFormat::Rss) => {
+            let channel = rss::Channel::read_from(body.as_bytes())?;
+            Ok(channel.items().iter().map(Item::from_rss).collect())
+        },
+        Some(FeedFormat::Atom) => {
+            // atom_syndication's errors only implement failure::Fail, not Error
+            let feed = atom::Feed::read_from(body.as_bytes())
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+            Ok(feed.entries().iter().map(Item::from_atom).collect())
+        },
+        Some(FeedFormat::Json) => json_feed::parse_json_feed(body),
+        None => {
+            Err(Box::new(io::Error::new(ErrorKind::InvalidData, "Unrecognized feed format")))
+        }
+    }
+}
+
+/// Download and parse a feed.
+pub fn fetch_feed(url: &Url) -> Result<Vec<Item>, Box<Error>> {
+    debug!("Fetching feed {}", RedactedUrl(url));
+
+    let mut response = reqwest::get(url.clone())?;
+    if !response.status().is_success() {
+        return Err(Box::new(io::Error::new(ErrorKind::Other,
+                                           format!("Server returned {}", response.status()))));
+    }
+
+    let mut body = String::new();
+    response.read_to_string(&mut body)?;
+
+    parse_feed(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{DateTime, FixedOffset};
+
+    const RSS2: &str = include_str!("../test/rss2.xml");
+    const RDF: &str = include_str!("../test/rdf.xml");
+    const ATOM: &str = include_str!("../test/atom.xml");
+    const JSON: &str = include_str!("../test/feed.json");
+
+    fn date(d: &str) -> Option<DateTime<FixedOffset>> {
+        Some(DateTime::parse_from_rfc3339(d).unwrap())
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(detect_format(RSS2), Some(FeedFormat::Rss));
+        assert_eq!(detect_format(RDF), Some(FeedFormat::Rss));
+        assert_eq!(detect_format(ATOM), Some(FeedFormat::Atom));
+        assert_eq!(detect_format(JSON), Some(FeedFormat::Json));
+    }
+
+    #[test]
+    fn detects_formats_after_prologs() {
+        assert_eq!(detect_format("\u{feff}<?xml version=\"1.0\"?>\n<!DOCTYPE rss>\n<rss>"),
+                   Some(FeedFormat::Rss));
+        assert_eq!(detect_format("<!-- <feed> -->\n<atom:feed xmlns:atom=\"x\">"),
+                   Some(FeedFormat::Atom));
+        assert_eq!(detect_format("  \n{\"version\": \"\"}"), Some(FeedFormat::Json));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert_eq!(detect_format("<html><body></body></html>"), None);
+        assert_eq!(detect_format("not a feed"), None);
+        assert_eq!(detect_format("<!-- unterminated"), None);
+        assert!(parse_feed("<html></html>").is_err());
+    }
+
+    #[test]
+    fn parses_rss2() {
+        let items = parse_feed(RSS2).unwrap();
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].title, "Some Show S01E01 720p HDTV x264");
+        assert_eq!(items[0].id.as_ref().unwrap(), "https://tracker.example/details/1");
+        assert_eq!(items[0].date(), date("2018-10-16T08:30:00Z"));
+        assert_eq!(items[0].categories, vec!["TV"]);
+        assert_eq!(items[0].size, Some(734003200));
+        assert_eq!(items[0].download_url(), Some("https://tracker.example/download/1.torrent"));
+
+        assert_eq!(items[1].date(), None);
+        assert_eq!(items[1].info_hash.as_ref().unwrap(), "0123456789abcdef0123456789abcdef01234567");
+    }
+
+    #[test]
+    fn parses_rdf_with_dc_date() {
+        let items = parse_feed(RDF).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, "Some Show S01E03 720p HDTV x264");
+        assert_eq!(items[0].published, date("2018-10-17T08:30:00+02:00"));
+        assert_eq!(items[0].download_url(), Some("https://tracker.example/download/3.torrent"));
+    }
+
+    #[test]
+    fn parses_atom_with_updated_fallback() {
+        let items = parse_feed(ATOM).unwrap();
+        assert_eq!(items.len(), 2);
+
+        // published is preferred over updated
+        assert_eq!(items[0].date(), date("2018-10-18T08:30:00Z"));
+        assert_eq!(items[0].links, vec!["https://tracker.example/details/4"]);
+        assert_eq!(items[0].size, Some(734003200));
+        assert_eq!(items[0].download_url(), Some("https://tracker.example/download/4.torrent"));
+
+        assert_eq!(items[1].published, None);
+        assert_eq!(items[1].date(), date("2018-10-19T08:30:00Z"));
+    }
+
+    #[test]
+    fn parses_json_feed() {
+        let items = parse_feed(JSON).unwrap();
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].id.as_ref().unwrap(), "6");
+        assert_eq!(items[0].date(), date("2018-10-20T08:30:00Z"));
+        assert_eq!(items[0].categories, vec!["TV"]);
+        assert_eq!(items[0].size, Some(734003200));
+        assert_eq!(items[0].download_url(), Some("https://tracker.example/download/6.torrent"));
+
+        assert_eq!(items[1].published, None);
+        assert_eq!(items[1].date(), date("2018-10-21T08:30:00Z"));
+    }
+
+    #[test]
+    fn rejects_unknown_json_feed_versions() {
+        assert!(parse_feed("{\"version\": \"https://example.com/v1\", \"items\": []}").is_err());
+    }
+}
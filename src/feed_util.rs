@@ -1,16 +1,25 @@
 use std::collections::HashSet;
 use std::error::Error;
-use std::path::Path;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write, ErrorKind};
 
-use std::io::Write;
-
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Local};
 
 use csv;
 
+use serde_json;
+
 use reqwest::Url;
 
+use db_util;
+
+use filter;
+use item::Item;
+use redact::RedactedUrl;
+
 
 pub type Feeds = HashSet<Feed>;
 
@@ -18,40 +27,217 @@ pub type Feeds = HashSet<Feed>;
 // impl'ing Serialize and Deserialize but since Url is the only thing that doesn't have a
 // ser/de impl already it might not be that difficult. 
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// `id` is a short identifier assigned when the feed is added which never changes afterwards.
+/// Two feeds are considered the same if they have the same url and the same filters after
+/// normalizing them with `normalize_filters`, regardless of any of the other fields.
+/// `failures` is the number of updates in a row in which the feed could not be fetched, and
+/// `last_error` is why it couldn't be fetched the last time that happened. `last_match` is when
+/// an item last matched the filters. If `alias` is set, the feed follows that alias: changing the
+/// alias's url changes the feed's url too. `added` is when the feed was added and `last_fetch`
+/// when it was last fetched successfully; both are unknown for feeds from before they were kept.
+/// Unlike `last_update`, which can be set by hand, they are only set by rss-torrent itself.
+#[derive(Debug, Clone)]
 pub struct Feed {
+    pub id: String,
     pub url: Url,
     pub last_update: Option<DateTime<FixedOffset>>,
     pub filters: Vec<String>,
+    pub paused: bool,
+    pub failures: u32,
+    pub last_error: Option<String>,
+    pub last_match: Option<DateTime<FixedOffset>>,
+    pub alias: Option<String>,
+    pub added: Option<DateTime<FixedOffset>>,
+    pub last_fetch: Option<DateTime<FixedOffset>>,
+}
+
+impl PartialEq for Feed {
+    fn eq(&self, other: &Feed) -> bool {
+        self.url == other.url && self.normalized_filters() == other.normalized_filters()
+    }
+}
+
+impl Eq for Feed {}
+
+impl Hash for Feed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.url.hash(state);
+        self.normalized_filters().hash(state);
+    }
+}
+
+impl fmt::Display for Feed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {} with filters \"{}\"", self.id, RedactedUrl(&self.url), self.filters.join(","))
+    }
 }
 
 impl Feed {
-    pub fn to_record(self) -> FeedRecord {
-   
-        // workaround for https://github.com/BurntSushi/rust-csv/issues/110
-        let filters: Option<Vec<String>>;
-        if self.filters.len() > 0 {filters = Some(self.filters);}
-        else { filters = None; }
+    /// Create a new feed with an id that isn't used by any of `feeds`.
+    pub fn new(url: Url, filters: Vec<String>, feeds: &Feeds) -> Feed {
+        let now = Local::now();
+        let mut feed = Feed {
+            id: String::new(),
+            url: url,
+            last_update: None,
+            filters: filters,
+            paused: false,
+            failures: 0,
+            last_error: None,
+            last_match: None,
+            alias: None,
+            added: Some(now.with_timezone(now.offset())),
+            last_fetch: None,
+        };
+        feed.id = unique_id(&feed, feeds);
+        feed
+    }
+
+    pub fn normalized_filters(&self) -> Vec<String> {
+        normalize_filters(&self.filters)
+    }
+
+    /// Whether each of `filters` is one of this feed's filters, after normalizing both.
+    pub fn has_filters(&self, filters: &[String]) -> bool {
+        let own = self.normalized_filters();
+        normalize_filters(filters).iter().all(|f| own.contains(f))
+    }
 
-        FeedRecord {
+    /// An item matches if every word of any one of the filters matches it. See
+    /// `filter::Condition` for what a word can be. A feed without filters matches everything.
+    pub fn matches(&self, item: &Item) -> bool {
+        if self.filters.is_empty() {
+            return true;
+        }
+
+        self.filters.iter().any(|filter| filter::filter_matches(filter, item))
+    }
+
+    /// Whether `item` was published after the last time this feed was updated. Items without a
+    /// date are always considered new, the history keeps them from being added twice.
+    pub fn is_new(&self, item: &Item) -> bool {
+        match (self.last_update, item.date()) {
+            (Some(last), Some(date)) => date > last,
+            _ => true,
+        }
+    }
+
+    pub fn to_record(self) -> Result<FeedRecord, Box<Error>> {
+        Ok(FeedRecord {
+            id: self.id,
             url: self.url.to_string(),
             last_update: self.last_update,
-            filters: filters,
+            filters: serde_json::to_string(&self.filters)?,
+            paused: self.paused,
+            failures: self.failures,
+            last_error: self.last_error,
+            last_match: self.last_match,
+            alias: self.alias,
+            added: self.added,
+            last_fetch: self.last_fetch,
+        })
+    }
+}
+
+/// Filters compare equal if they contain the same words, ignoring case, order and duplicates,
+/// since that's what `Feed::matches` does.
+pub fn normalize_filters(filters: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = filters.iter()
+        .map(|filter| {
+            let mut words: Vec<String> = filter.split_whitespace().map(|w| w.to_lowercase()).collect();
+            words.sort();
+            words.dedup();
+            words.join(" ")
+        })
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+/// Length of the hex ids given to feeds.
+const FEED_ID_LEN: usize = 8;
+
+// FNV-1a, which unlike `DefaultHasher` is guaranteed to give the same hash in every version.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME))
+}
+
+/// Derive an id from the feed's url and normalized filters, so that the same feed gets the same
+/// id if it is deleted and added again. In the unlikely case that the id is already taken by
+/// another feed in `feeds`, a different one is picked.
+pub fn unique_id(feed: &Feed, feeds: &Feeds) -> String {
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, feed.url.as_str().as_bytes());
+    for filter in feed.normalized_filters() {
+        // separate the fields so that moving a word between filters changes the hash
+        hash = fnv1a(hash, b"\0");
+        hash = fnv1a(hash, filter.as_bytes());
+    }
+
+    let mut salt: u64 = 0;
+    loop {
+        let salted = if salt == 0 { hash } else { fnv1a(hash, format!("\0{}", salt).as_bytes()) };
+
+        let id = format!("{:016x}", salted)[..FEED_ID_LEN].to_string();
+        if !feeds.iter().any(|f| f.id == id && f != feed) {
+            return id;
         }
+        salt += 1;
     }
 }
 
+/// `filters` is a JSON list of strings, so that filters containing commas, quotes or nothing at
+/// all survive the round trip through the csv file.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FeedRecord {
+    pub id: String,
     pub url: String,
     pub last_update: Option<DateTime<FixedOffset>>,
-    pub filters: Option<Vec<String>>,
+    pub filters: String,
+    pub paused: bool,
+    pub failures: u32,
+    pub last_error: Option<String>,
+    pub last_match: Option<DateTime<FixedOffset>>,
+    pub alias: Option<String>,
+    pub added: Option<DateTime<FixedOffset>>,
+    pub last_fetch: Option<DateTime<FixedOffset>>,
 }
 
 impl FeedRecord {
     pub fn to_feed(self) -> Result<Feed, Box<Error>> {
         let url = Url::parse(&self.url)?;
 
+        Ok(Feed {
+            id: self.id,
+            url: url,
+            last_update: self.last_update,
+            filters: serde_json::from_str(&self.filters)?,
+            paused: self.paused,
+            failures: self.failures,
+            last_error: self.last_error,
+            last_match: self.last_match,
+            alias: self.alias,
+            added: self.added,
+            last_fetch: self.last_fetch,
+        })
+    }
+}
+
+/// Version 1 of feeds.csv stored the filters as a variable number of trailing fields.
+#[derive(Clone, Debug, Deserialize)]
+struct LegacyFeedRecord {
+    url: String,
+    last_update: Option<DateTime<FixedOffset>>,
+    filters: Option<Vec<String>>,
+}
+
+impl LegacyFeedRecord {
+    fn to_feed(self) -> Result<Feed, Box<Error>> {
+        let url = Url::parse(&self.url)?;
+
         // workaround for https://github.com/BurntSushi/rust-csv/issues/110
         let filters: Vec<String>;
         match self.filters {
@@ -60,92 +246,512 @@ impl FeedRecord {
         }
 
         Ok(Feed {
+            id: String::new(),
             url: url,
             last_update: self.last_update,
             filters: filters,
+            paused: false,
+            failures: 0,
+            last_error: None,
+            last_match: None,
+            alias: None,
+            added: None,
+            last_fetch: None,
         })
     }
 }
 
 
-// So yeah this is pretty much copied and pasted from alias_utils and they could be combined but it
-// would be a bit annoying because of the logging. It wouldn't be that hard but since there's only
-// two files it's fine.
+pub const FEED_DB_FILENAME: &str = "feeds.csv";
 
-const FEED_DB_FILENAME: &str = "feeds.csv";
+// The first line of feeds.csv is `FEED_DB_MAGIC` followed by the format version, and the second is
+// the header row. Version 1 files are the original format, which has neither.
+const FEED_DB_MAGIC: &str = "#rss-torrent feeds.csv version ";
+const FEED_DB_VERSION: u32 = 2;
+const FEED_DB_HEADERS: &[&str] = &["id", "url", "last_update", "filters", "paused", "failures",
+                                   "last_error", "last_match", "alias", "added", "last_fetch"];
 
 /// Opens for read write and create because it's simpler
 fn open_or_create_feed_db(data_dir: &Path) -> Result<File, Box<Error>> {
-    trace!("Opening feeds file.");
-
-    let mut db_path = data_dir.to_path_buf();
-    db_path.push(FEED_DB_FILENAME);
-
-    if !db_path.exists() {
-        warn!("Feeds db not found at {}, creating.", db_path.to_string_lossy());
-    }
-    else {
-        trace!("Feeds db found at {}.", db_path.to_string_lossy());
-    }
-
     Ok(OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open(db_path)?)
+        .truncate(false)
+        .open(db_util::db_path(data_dir, FEED_DB_FILENAME, "Feeds"))?)
 }
 
-/// Read feed db or create if it does not exist.
-pub fn read_feed_db(data_dir: &Path) -> Result<Feeds, Box<Error>> {
-    debug!("Reading feed db.");
+/// Split off the version line, returning the version and the rest of the file.
+fn split_version(contents: &str) -> Result<(u32, &str), Box<Error>> {
+    if !contents.starts_with(FEED_DB_MAGIC) {
+        return Ok((1, contents));
+    }
+
+    let (first_line, rest) = match contents.find('\n') {
+        Some(i) => (&contents[..i], &contents[i + 1..]),
+        None => (contents, ""),
+    };
+
+    let version = first_line[FEED_DB_MAGIC.len()..].trim().parse::<u32>()
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData,
+                                    format!("Invalid feeds db version line \"{}\": {}", first_line, e)))?;
+
+    Ok((version, rest))
+}
+
+fn check_headers<R: io::Read>(reader: &mut csv::Reader<R>, expected: &[&str]) -> Result<(), Box<Error>> {
+    let headers = reader.headers()?.clone();
+    if headers.iter().take(expected.len()).ne(expected.iter().cloned()) {
+        return Err(Box::new(io::Error::new(ErrorKind::InvalidData,
+            format!("Unexpected feeds db headers: {}", headers.iter().collect::<Vec<_>>().join(",")))));
+    }
+
+    Ok(())
+}
 
+/// Parse a feeds db in the current version.
+fn parse_records(body: &str) -> Result<Feeds, Box<Error>> {
     let mut feeds = Feeds::new();
 
-    let db_file = open_or_create_feed_db(data_dir)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .from_reader(body.as_bytes());
+    check_headers(&mut reader, FEED_DB_HEADERS)?;
+
+    for line in reader.deserialize() {
+        let record: FeedRecord = line?;
+        let feed = record.to_feed()?;
+        trace!("Deserialized feed with url {}", RedactedUrl(&feed.url));
+
+        insert_merging(&mut feeds, feed);
+    }
+
+    Ok(feeds)
+}
+
+/// Add `feed` to `feeds`. Feeds.csv can contain equal feeds if it was edited by hand or written by
+/// a version that compared filters differently. Those are merged into the first one: it keeps the
+/// newest `last_update` and `last_match`, and is paused if either of them is.
+fn insert_merging(feeds: &mut Feeds, feed: Feed) {
+    let merged = match feeds.take(&feed) {
+        Some(mut existing) => {
+            warn!("Feeds db has the same feed twice, merging {} into {}.", feed, existing);
+            existing.last_update = existing.last_update.max(feed.last_update);
+            existing.last_match = existing.last_match.max(feed.last_match);
+            existing.paused = existing.paused || feed.paused;
+            existing
+        },
+        None => feed,
+    };
+
+    feeds.insert(merged);
+}
+
+/// Give feeds from version 1, which didn't have ids, an id.
+fn assign_ids(feeds: Feeds) -> Feeds {
+    let mut with_ids = Feeds::new();
+    for mut feed in feeds {
+        feed.id = unique_id(&feed, &with_ids);
+        with_ids.insert(feed);
+    }
+
+    with_ids
+}
+
+/// Parse a version 1 feeds db.
+fn parse_legacy_records(body: &str) -> Result<Feeds, Box<Error>> {
+    let mut feeds = Feeds::new();
 
     let mut reader = csv::ReaderBuilder::new()
         .has_headers(false)
         .flexible(true) // may have any number of filters
-        .from_reader(db_file);
+        .from_reader(body.as_bytes());
 
-    for line in reader.deserialize() {
-        let record: FeedRecord = line?;
+    // The filters are a variable number of trailing fields, which can't be deserialized by header
+    // name, so always deserialize by position.
+    for line in reader.records() {
+        let record: LegacyFeedRecord = line?.deserialize(None)?;
         let feed = record.to_feed()?;
-        trace!("Deserialized feed with url {}", feed.url.as_str());
+        trace!("Deserialized feed with url {}", RedactedUrl(&feed.url));
 
-        feeds.insert(feed);
+        insert_merging(&mut feeds, feed);
     }
 
-    return Ok(feeds);
+    Ok(assign_ids(feeds))
+}
+
+/// Copy feeds.csv aside, see `db_util::backup_file`. Returns `None` if there is nothing to back
+/// up.
+pub fn backup_feed_db(data_dir: &Path, tag: &str) -> Result<Option<PathBuf>, Box<Error>> {
+    db_util::backup_file(&feed_db_path(data_dir), tag)
 }
 
-/// Write feed db or create if it does not exist.
+fn feed_db_path(data_dir: &Path) -> PathBuf {
+    let mut db_path = data_dir.to_path_buf();
+    db_path.push(FEED_DB_FILENAME);
+    db_path
+}
+
+fn read_contents(path: &Path) -> Result<String, Box<Error>> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn newer_version_error(version: u32) -> Box<Error> {
+    Box::new(io::Error::new(ErrorKind::InvalidData,
+        format!("Feeds db has version {} but this version of rss-torrent only understands up to \
+                 version {}", version, FEED_DB_VERSION)))
+}
+
+/// Read feeds.csv, or return no feeds if it does not exist. Reading never changes the file:
+/// files in an older format are only converted in memory and are upgraded by the next
+/// `write_feed_db`. Files in a newer format than we understand are refused.
+pub fn read_feed_db(data_dir: &Path) -> Result<Feeds, Box<Error>> {
+    debug!("Reading feed db.");
+
+    let db_path = feed_db_path(data_dir);
+    if !db_path.exists() {
+        debug!("Feeds db not found at {}.", db_path.to_string_lossy());
+        return Ok(Feeds::new());
+    }
+
+    let contents = read_contents(&db_path)?;
+    let (version, body) = split_version(&contents)?;
+    trace!("Feeds db has version {}", version);
+
+    match version {
+        1 => parse_legacy_records(body),
+        2 => parse_records(body),
+        _ => Err(newer_version_error(version)),
+    }
+}
+
+/// Back up an existing feeds.csv in an older format before it is overwritten with the current
+/// one. Refuses to overwrite a file in a newer format.
+fn prepare_upgrade(data_dir: &Path) -> Result<(), Box<Error>> {
+    let db_path = feed_db_path(data_dir);
+    if !db_path.exists() {
+        return Ok(());
+    }
+
+    let contents = read_contents(&db_path)?;
+    // an empty file just gets the current format
+    if contents.trim().is_empty() {
+        return Ok(());
+    }
+
+    let (version, _) = split_version(&contents)?;
+    if version > FEED_DB_VERSION {
+        return Err(newer_version_error(version));
+    }
+    if version < FEED_DB_VERSION {
+        let backup_path = backup_feed_db(data_dir, &format!("v{}", version))?.unwrap_or_default();
+        info!("Upgrading feeds db from version {} to {}. The old db was backed up to {}.",
+              version, FEED_DB_VERSION, backup_path.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+/// Write feed db or create if it does not exist. A file in an older format is backed up first.
 pub fn write_feed_db(data_dir: &Path, mut feeds: Feeds) -> Result<(), Box<Error>> {
     debug!("Writing feed db.");
 
+    prepare_upgrade(data_dir)?;
+
     let mut db_file = open_or_create_feed_db(data_dir)?;
 
     let mut buf = Vec::new();
+    writeln!(buf, "{}{}", FEED_DB_MAGIC, FEED_DB_VERSION)?;
     {
+        // serialize only writes the header row along with the first record, so write it ourselves
+        // in case there are no feeds
         let mut writer = csv::WriterBuilder::new()
             .has_headers(false)
-            .flexible(true) // may have any number of filters
             .from_writer(&mut buf);
 
+        writer.write_record(FEED_DB_HEADERS)?;
         for feed in feeds.drain() {
-            trace!("Serializing feed {}", feed.url.as_str());
-            writer.serialize(feed.to_record())?;
+            trace!("Serializing feed {}", RedactedUrl(&feed.url));
+            writer.serialize(feed.to_record()?)?;
         }
     }
   
-    // there should be a better way of doing this
-    let result = db_file.write_all(&mut buf).map_err(|e| {
-        error!("Error writing to file. DATA MAY BE CORRUPTED!");
-        e
-    })
-    .and_then(|_| {
-        db_file.set_len(buf.len() as u64)
-    });
-
-    Ok(result?)
+    db_util::overwrite_db(&mut db_file, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    use quickcheck::{Arbitrary, Gen, QuickCheck};
+
+    use tempdir::TempDir;
+
+    const URL: &str = "https://t.example/rss";
+    const LAST_UPDATE: &str = "2018-10-15T10:00:00+00:00";
+
+    // the original format, without a version line or header row
+    const V1: &str = "https://t.example/rss,2018-10-15T10:00:00+00:00,show 1080p\n";
+
+    fn temp_dir() -> TempDir {
+        TempDir::new("rss-torrent").unwrap()
+    }
+
+    fn db_contents(dir: &TempDir) -> String {
+        read_contents(&feed_db_path(dir.path())).unwrap()
+    }
+
+    fn only_feed(feeds: Feeds) -> Feed {
+        assert_eq!(feeds.len(), 1);
+        feeds.into_iter().next().unwrap()
+    }
+
+    fn check_v1_feed(feed: &Feed) {
+        assert_eq!(feed.url.as_str(), URL);
+        assert_eq!(feed.filters, vec!["show 1080p"]);
+        assert_eq!(feed.last_update, Some(DateTime::parse_from_rfc3339(LAST_UPDATE).unwrap()));
+        assert_eq!(feed.id.len(), FEED_ID_LEN);
+        assert!(!feed.paused);
+        assert_eq!(feed.failures, 0);
+    }
+
+    #[test]
+    fn missing_db_is_empty_and_not_created() {
+        let dir = temp_dir();
+        assert!(read_feed_db(dir.path()).unwrap().is_empty());
+        assert!(!feed_db_path(dir.path()).exists());
+    }
+
+    #[test]
+    fn reads_old_versions_without_changing_them() {
+        let dir = temp_dir();
+        fs::write(feed_db_path(dir.path()), V1).unwrap();
+
+        check_v1_feed(&only_feed(read_feed_db(dir.path()).unwrap()));
+
+        assert_eq!(db_contents(&dir), V1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn writing_upgrades_old_versions_after_a_backup() {
+        let dir = temp_dir();
+        fs::write(feed_db_path(dir.path()), V1).unwrap();
+
+        let feeds = read_feed_db(dir.path()).unwrap();
+        write_feed_db(dir.path(), feeds.clone()).unwrap();
+
+        let upgraded = db_contents(&dir);
+        assert!(upgraded.starts_with(&format!("{}{}\n", FEED_DB_MAGIC, FEED_DB_VERSION)));
+        let feed = only_feed(read_feed_db(dir.path()).unwrap());
+        check_v1_feed(&feed);
+        assert_eq!(feed.id, only_feed(feeds).id);
+
+        let mut backup = dir.path().to_path_buf();
+        backup.push(format!("{}.v1.bak", FEED_DB_FILENAME));
+        assert_eq!(read_contents(&backup).unwrap(), V1);
+
+        // the current version isn't backed up again
+        write_feed_db(dir.path(), Feeds::new()).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let dir = temp_dir();
+        let contents = format!("{}{}\nid,url\n", FEED_DB_MAGIC, FEED_DB_VERSION + 1);
+        fs::write(feed_db_path(dir.path()), &contents).unwrap();
+
+        assert!(read_feed_db(dir.path()).is_err());
+        assert!(write_feed_db(dir.path(), Feeds::new()).is_err());
+        assert_eq!(db_contents(&dir), contents);
+    }
+
+    #[test]
+    fn writes_new_dbs_in_the_current_version() {
+        let dir = temp_dir();
+        let mut feeds = Feeds::new();
+        let filters = vec!["show 1080p".to_string()];
+        feeds.insert(Feed::new(Url::parse(URL).unwrap(), filters, &Feeds::new()));
+
+        write_feed_db(dir.path(), feeds.clone()).unwrap();
+
+        assert!(db_contents(&dir).starts_with(&format!("{}{}\n", FEED_DB_MAGIC, FEED_DB_VERSION)));
+        assert_eq!(read_feed_db(dir.path()).unwrap(), feeds);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    // pieces that are likely to break the csv or JSON quoting
+    const AWKWARD_TEXT: &[&str] = &[
+        "", " ", ",", "\"", "\"\"", "'", "\n", "\r\n", "\\", "[", "]", "[\"\"]", "#", "é", "日本語",
+        "\u{1f980}", "\u{feff}", "\u{0}",
+    ];
+
+    fn awkward_string<G: Gen>(g: &mut G) -> String {
+        let mut s = String::new();
+        for _ in 0..g.gen_range(0, 4) {
+            if g.gen() {
+                s.push_str(AWKWARD_TEXT[g.gen_range(0, AWKWARD_TEXT.len())]);
+            }
+            else {
+                s.push_str(&String::arbitrary(g));
+            }
+        }
+        s
+    }
+
+    /// Empty strings are written the same as `None`, and are never stored anyway, so this only
+    /// returns non-empty strings.
+    fn arbitrary_text<G: Gen>(g: &mut G) -> Option<String> {
+        if g.gen() {
+            let s = awkward_string(g);
+            Some(if s.is_empty() { "x".to_string() } else { s })
+        }
+        else {
+            None
+        }
+    }
+
+    fn arbitrary_date<G: Gen>(g: &mut G) -> Option<DateTime<FixedOffset>> {
+        if g.gen() {
+            // offsets are written with minute precision
+            let offset = FixedOffset::east(g.gen_range(-12 * 60, 14 * 60) * 60);
+            Some(offset.timestamp(g.gen_range(0, 4_000_000_000), 0))
+        }
+        else {
+            None
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct ArbitraryFeed(Feed);
+
+    impl Arbitrary for ArbitraryFeed {
+        fn arbitrary<G: Gen>(g: &mut G) -> ArbitraryFeed {
+            let mut url = Url::parse(URL).unwrap();
+            url.query_pairs_mut().append_pair("q", &awkward_string(g));
+
+            let mut filters = Vec::new();
+            for _ in 0..g.gen_range(0, 4) {
+                filters.push(awkward_string(g));
+            }
+
+            ArbitraryFeed(Feed {
+                id: format!("{:08x}", g.gen::<u32>()),
+                url: url,
+                last_update: arbitrary_date(g),
+                filters: filters,
+                paused: g.gen(),
+                failures: g.gen(),
+                last_error: arbitrary_text(g),
+                last_match: arbitrary_date(g),
+                alias: arbitrary_text(g),
+                added: arbitrary_date(g),
+                last_fetch: arbitrary_date(g),
+            })
+        }
+    }
+
+    fn round_trips(feed: ArbitraryFeed) -> bool {
+        let feed = feed.0;
+        let dir = temp_dir();
+
+        let mut feeds = Feeds::new();
+        feeds.insert(feed.clone());
+        write_feed_db(dir.path(), feeds).unwrap();
+        let read = only_feed(read_feed_db(dir.path()).unwrap());
+
+        read.url == feed.url && read.filters == feed.filters && read.last_update == feed.last_update
+            && read.id == feed.id && read.paused == feed.paused && read.failures == feed.failures
+            && read.last_error == feed.last_error && read.last_match == feed.last_match
+            && read.alias == feed.alias && read.added == feed.added
+            && read.last_fetch == feed.last_fetch
+    }
+
+    #[test]
+    fn feeds_round_trip_through_the_db() {
+        QuickCheck::new().tests(200).quickcheck(round_trips as fn(ArbitraryFeed) -> bool);
+    }
+
+    #[test]
+    fn merges_duplicate_feeds_on_read() {
+        let dir = temp_dir();
+        fs::write(feed_db_path(dir.path()), "#rss-torrent feeds.csv version 2\n\
+            id,url,last_update,filters,paused,failures,last_error,last_match,alias,added,last_fetch\n\
+            0a1b2c3d,https://t.example/rss,2018-10-15T10:00:00+00:00,\"[\"\"show 1080p\"\"]\",false,0,,,,,\n\
+            4e5f6a7b,https://t.example/rss,2018-10-16T10:00:00+00:00,\"[\"\"1080p Show\"\"]\",true,0,,,,,\n\
+            8c9d0e1f,https://t.example/rss,2018-10-14T10:00:00+00:00,\"[\"\"other\"\"]\",false,0,,,,,\n")
+            .unwrap();
+
+        let feeds = read_feed_db(dir.path()).unwrap();
+        assert_eq!(feeds.len(), 2);
+
+        let merged = feeds.iter().find(|f| f.id == "0a1b2c3d").unwrap();
+        assert_eq!(merged.filters, vec!["show 1080p"]);
+        assert_eq!(merged.last_update,
+                   Some(DateTime::parse_from_rfc3339("2018-10-16T10:00:00+00:00").unwrap()));
+        assert!(merged.paused);
+    }
+
+    #[test]
+    fn merges_duplicate_legacy_feeds_on_read() {
+        let dir = temp_dir();
+        fs::write(feed_db_path(dir.path()), "https://t.example/rss,2018-10-15T10:00:00+00:00,show\n\
+                                             https://t.example/rss,2018-10-16T10:00:00+00:00,SHOW\n")
+            .unwrap();
+
+        let feed = only_feed(read_feed_db(dir.path()).unwrap());
+        assert_eq!(feed.last_update,
+                   Some(DateTime::parse_from_rfc3339("2018-10-16T10:00:00+00:00").unwrap()));
+    }
+
+    fn feed_with(url: &str, filters: &[&str]) -> Feed {
+        Feed {
+            id: String::new(),
+            url: Url::parse(url).unwrap(),
+            last_update: None,
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+            paused: false,
+            failures: 0,
+            last_error: None,
+            last_match: None,
+            alias: None,
+            added: None,
+            last_fetch: None,
+        }
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        // changing these changes the id of every feed added from now on
+        assert_eq!(unique_id(&feed_with(URL, &[]), &Feeds::new()), "eae66e0f");
+        assert_eq!(unique_id(&feed_with(URL, &["show 1080p"]), &Feeds::new()), "b4fecfe8");
+    }
+
+    #[test]
+    fn ids_depend_on_normalized_filters() {
+        let id = unique_id(&feed_with(URL, &["show 1080p"]), &Feeds::new());
+        assert_eq!(unique_id(&feed_with(URL, &["1080p  SHOW"]), &Feeds::new()), id);
+        assert!(unique_id(&feed_with(URL, &["show", "1080p"]), &Feeds::new()) != id);
+        let other = feed_with("https://other.example/rss", &["show 1080p"]);
+        assert!(unique_id(&other, &Feeds::new()) != id);
+    }
+
+    #[test]
+    fn ids_avoid_other_feeds() {
+        let feed = feed_with(URL, &["show"]);
+        let mut taken = feed_with(URL, &["other"]);
+        taken.id = unique_id(&feed, &Feeds::new());
+
+        let mut feeds = Feeds::new();
+        feeds.insert(taken.clone());
+        let id = unique_id(&feed, &feeds);
+        assert!(id != taken.id);
+        assert_eq!(id.len(), FEED_ID_LEN);
+    }
 }
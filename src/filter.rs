@@ -0,0 +1,231 @@
+use item::Item;
+
+/// Comparison used by attribute conditions like `seeders>=5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Op {
+    fn compare(self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            Op::Lt => lhs < rhs,
+            Op::Le => lhs <= rhs,
+            Op::Eq => lhs == rhs,
+            Op::Ge => lhs >= rhs,
+            Op::Gt => lhs > rhs,
+        }
+    }
+}
+
+/// A single word of a filter. Most words are just matched against the title, but words that look
+/// like `seeders>=5`, `size<4GiB`, `category:5040` or `imdbid:tt0944947` are checked against the
+/// item's attributes instead. Attribute conditions never match items that don't have the
+/// attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Word(String),
+    Seeders(Op, u64),
+    Size(Op, u64),
+    Category(String),
+    ImdbId(String),
+}
+
+const OPS: &[(&str, Op)] = &[
+    (">=", Op::Ge),
+    ("<=", Op::Le),
+    (">", Op::Gt),
+    ("<", Op::Lt),
+    ("=", Op::Eq),
+];
+
+impl Condition {
+    pub fn parse(word: &str) -> Condition {
+        let lower = word.to_lowercase();
+
+        if lower.starts_with("category:") {
+            return Condition::Category(word["category:".len()..].to_string());
+        }
+        if lower.starts_with("imdbid:") {
+            return Condition::ImdbId(lower["imdbid:".len()..].to_string());
+        }
+
+        for &(op_str, op) in OPS {
+            if let Some(pos) = lower.find(op_str) {
+                let (name, value) = (&lower[..pos], &lower[pos + op_str.len()..]);
+                let condition = match name {
+                    "seeders" => value.parse().ok().map(|v| Condition::Seeders(op, v)),
+                    "size" => parse_size(value).map(|v| Condition::Size(op, v)),
+                    _ => None,
+                };
+
+                if let Some(c) = condition {
+                    return c;
+                }
+                break;
+            }
+        }
+
+        Condition::Word(lower)
+    }
+
+    /// `title` is the item's title, lowercased.
+    pub fn matches(&self, item: &Item, title: &str) -> bool {
+        match *self {
+            Condition::Word(ref word) => title.contains(word.as_str()),
+            Condition::Seeders(op, n) => item.seeders.map_or(false, |s| op.compare(s, n)),
+            Condition::Size(op, n) => item.size.map_or(false, |s| op.compare(s, n)),
+            Condition::Category(ref cat) => item.categories.iter().any(|c| category_matches(cat, c)),
+            Condition::ImdbId(ref id) => item.imdb_id.as_ref().map_or(false, |i| i == id),
+        }
+    }
+}
+
+/// Torznab categories are hierarchical by thousands, e.g. 5040 (TV/HD) is in 5000 (TV), so a
+/// filter on a parent category matches all of its children.
+fn category_matches(wanted: &str, category: &str) -> bool {
+    if wanted.eq_ignore_ascii_case(category) {
+        return true;
+    }
+
+    match (wanted.parse::<u32>(), category.parse::<u32>()) {
+        (Ok(w), Ok(c)) => w % 1000 == 0 && w / 1000 == c / 1000,
+        _ => false,
+    }
+}
+
+/// Parse a size like `4GiB`, `700MB` or `1.5g` into bytes. Units without a `B` are binary.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_lowercase();
+    let split = size.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "tb" => 1000 * 1000 * 1000 * 1000,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Whether every word of `filter` matches `item`.
+pub fn filter_matches(filter: &str, item: &Item) -> bool {
+    let title = item.title.to_lowercase();
+    filter.split_whitespace()
+        .map(Condition::parse)
+        .all(|c| c.matches(item, &title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(title: &str) -> Item {
+        Item {
+            title: title.to_string(),
+            links: Vec::new(),
+            enclosures: Vec::new(),
+            id: None,
+            published: None,
+            updated: None,
+            categories: vec!["5040".to_string()],
+            size: Some(2 << 30),
+            seeders: Some(12),
+            info_hash: None,
+            magnet: None,
+            imdb_id: Some("tt0944947".to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_attribute_conditions() {
+        assert_eq!(Condition::parse("seeders>=5"), Condition::Seeders(Op::Ge, 5));
+        assert_eq!(Condition::parse("Seeders<10"), Condition::Seeders(Op::Lt, 10));
+        assert_eq!(Condition::parse("seeders=0"), Condition::Seeders(Op::Eq, 0));
+        assert_eq!(Condition::parse("size<4GiB"), Condition::Size(Op::Lt, 4 << 30));
+        assert_eq!(Condition::parse("size>700mb"), Condition::Size(Op::Gt, 700 * 1000 * 1000));
+        assert_eq!(Condition::parse("size<=1.5g"), Condition::Size(Op::Le, 3 << 29));
+        assert_eq!(Condition::parse("category:5040"), Condition::Category("5040".to_string()));
+        assert_eq!(Condition::parse("Category:TV"), Condition::Category("TV".to_string()));
+        assert_eq!(Condition::parse("imdbid:TT0944947"), Condition::ImdbId("tt0944947".to_string()));
+    }
+
+    #[test]
+    fn parses_other_words_as_title_words() {
+        assert_eq!(Condition::parse("1080p"), Condition::Word("1080p".to_string()));
+        assert_eq!(Condition::parse("WEB"), Condition::Word("web".to_string()));
+        assert_eq!(Condition::parse("a=b"), Condition::Word("a=b".to_string()));
+        assert_eq!(Condition::parse("x264=h264"), Condition::Word("x264=h264".to_string()));
+        assert_eq!(Condition::parse("seeders>=many"), Condition::Word("seeders>=many".to_string()));
+        assert_eq!(Condition::parse("size<huge"), Condition::Word("size<huge".to_string()));
+    }
+
+    #[test]
+    fn parses_size_units() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("100b"), Some(100));
+        assert_eq!(parse_size("1kb"), Some(1000));
+        assert_eq!(parse_size("1KiB"), Some(1024));
+        assert_eq!(parse_size("1k"), Some(1024));
+        assert_eq!(parse_size("700MB"), Some(700 * 1000 * 1000));
+        assert_eq!(parse_size("700mib"), Some(700 << 20));
+        assert_eq!(parse_size("4GB"), Some(4 * 1000 * 1000 * 1000));
+        assert_eq!(parse_size("4GiB"), Some(4 << 30));
+        assert_eq!(parse_size("1.5g"), Some(3 << 29));
+        assert_eq!(parse_size("2TB"), Some(2 * 1000 * 1000 * 1000 * 1000));
+        assert_eq!(parse_size("2t"), Some(2 << 40));
+        assert_eq!(parse_size(" 2 GiB "), Some(2 << 30));
+        assert_eq!(parse_size("2 parsecs"), None);
+        assert_eq!(parse_size("GiB"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn parent_categories_match_children() {
+        assert!(category_matches("5000", "5040"));
+        assert!(category_matches("5040", "5040"));
+        assert!(!category_matches("5040", "5000"));
+        assert!(!category_matches("5040", "5045"));
+        assert!(!category_matches("5000", "2040"));
+        assert!(category_matches("tv", "TV"));
+        assert!(!category_matches("TV", "TV/HD"));
+    }
+
+    #[test]
+    fn all_words_of_a_filter_must_match() {
+        let item = item("Some Show S02E05 1080p WEB h264");
+
+        assert!(filter_matches("some show 1080p", &item));
+        assert!(filter_matches("Some Show seeders>=5 size<4GiB category:5000", &item));
+        assert!(filter_matches("imdbid:tt0944947", &item));
+        assert!(!filter_matches("some show 2160p", &item));
+        assert!(!filter_matches("some show seeders>=20", &item));
+        assert!(!filter_matches("size<1GiB", &item));
+        assert!(!filter_matches("category:2000", &item));
+    }
+
+    #[test]
+    fn attribute_conditions_need_the_attribute() {
+        let mut item = item("Some Show");
+        item.seeders = None;
+        item.size = None;
+        item.imdb_id = None;
+
+        assert!(!filter_matches("seeders<5", &item));
+        assert!(!filter_matches("size<4GiB", &item));
+        assert!(!filter_matches("imdbid:tt0944947", &item));
+    }
+}
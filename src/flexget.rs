@@ -0,0 +1,276 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+
+use reqwest::Url;
+
+use serde_json::{Map, Value};
+use serde_yaml;
+
+use import::{Imported, ImportedFeed};
+use rule_util;
+
+// Plugins that only change what happens to accepted entries, so leaving them out doesn't change
+// what is matched. Any other plugin might reject entries, so tasks using one are not imported.
+const IGNORED_PLUGINS: &[&str] = &[
+    "aria2", "backlog", "clean_transmission", "convert_magnet", "deluge", "disable", "domain_delay",
+    "download", "email", "exec", "free_space", "headers", "magnets", "metainfo_quality",
+    "metainfo_series", "no_entries_ok", "notify", "priority", "pushbullet", "pushover",
+    "qbittorrent", "rtorrent", "seen", "seen_info_hash", "telegram", "template", "torrent_alive",
+    "transmission", "verify_ssl_certificates",
+];
+
+fn invalid(message: &str) -> Box<Error> {
+    Box::new(io::Error::new(ErrorKind::InvalidData, message.to_string()))
+}
+
+fn is_true(value: &Value) -> bool {
+    match *value {
+        Value::Bool(b) => b,
+        Value::String(ref s) => s == "yes" || s == "true" || s == "on",
+        _ => false,
+    }
+}
+
+fn as_list(value: &Value) -> Vec<&Value> {
+    match *value {
+        Value::Array(ref values) => values.iter().collect(),
+        Value::Null => Vec::new(),
+        ref v => vec![v],
+    }
+}
+
+/// Read the `rss` tasks of a FlexGet config. Each rss url of a task becomes a feed, with one
+/// filter per `series` show and `regexp` accept pattern. Tasks that use anything that can't be
+/// expressed as filters, like `reject` patterns, are left out entirely since importing them
+/// would download more than FlexGet does.
+pub fn parse_flexget(contents: &str) -> Result<Imported, Box<Error>> {
+    // read as json values, since FlexGet configs only use string keys
+    let config: Value = serde_yaml::from_str(contents)?;
+
+    let empty = Map::new();
+    let templates = config.get("templates").and_then(Value::as_object).unwrap_or(&empty);
+    let tasks = match config.get("tasks").and_then(Value::as_object) {
+        Some(tasks) => tasks,
+        None => return Err(invalid("Not a FlexGet config, it has no tasks")),
+    };
+
+    let mut imported = Imported::default();
+    for (name, task) in tasks {
+        let task = match task.as_object() {
+            Some(task) => with_templates(task, templates),
+            None => {
+                imported.unsupported.push(format!("task {}: it is not a mapping", name));
+                continue;
+            }
+        };
+
+        match translate_task(&task, &mut imported.unsupported, name) {
+            Ok((urls, filters)) => {
+                for url in urls {
+                    imported.feeds.push(ImportedFeed {
+                        url: url,
+                        filters: filters.clone(),
+                        last_update: None,
+                        alias: None,
+                    });
+                }
+            },
+            Err(reason) => imported.unsupported.push(format!("task {}: {}", name, reason)),
+        }
+    }
+
+    Ok(imported)
+}
+
+/// The task with the keys of its templates (and the `global` template, unless templates are
+/// disabled) that it doesn't set itself. FlexGet merges nested settings too, this only merges the
+/// top level.
+fn with_templates(task: &Map<String, Value>, templates: &Map<String, Value>) -> Map<String, Value> {
+    let disabled = match task.get("template") {
+        Some(&Value::Bool(false)) => true,
+        Some(&Value::String(ref s)) => s == "no",
+        _ => false,
+    };
+
+    let mut names: Vec<&str> = Vec::new();
+    if !disabled {
+        if let Some(value) = task.get("template") {
+            names.extend(as_list(value).iter().filter_map(|v| v.as_str()));
+        }
+        names.push("global");
+    }
+
+    let mut merged = task.clone();
+    for name in names {
+        if let Some(template) = templates.get(name).and_then(Value::as_object) {
+            for (key, value) in template {
+                merged.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+
+    merged
+}
+
+/// The rss urls and filters of a task, or why it can't be imported. Patterns that are left out
+/// without skipping the task are added to `notes`.
+fn translate_task(task: &Map<String, Value>, notes: &mut Vec<String>, name: &str)
+    -> Result<(Vec<Url>, Vec<String>), String> {
+    let mut urls = Vec::new();
+    let mut accept: Vec<Vec<String>> = Vec::new();
+    let mut accept_all = false;
+    let mut conditions = Vec::new();
+
+    for (plugin, value) in task {
+        match plugin.as_str() {
+            "rss" => urls.push(rss_url(value)?),
+            "inputs" => {
+                for input in as_list(value) {
+                    match input.get("rss") {
+                        Some(rss) => urls.push(rss_url(rss)?),
+                        None => notes.push(format!("task {}: only rss inputs can be imported", name)),
+                    }
+                }
+            },
+            "accept_all" => accept_all = is_true(value),
+            "series" => accept.extend(series_filters(value, notes, name)?),
+            "regexp" => accept.extend(regexp_filters(value, notes, name)?),
+            "content_size" => {
+                // FlexGet sizes are in MiB
+                for &(key, op) in &[("min", ">="), ("max", "<=")] {
+                    if let Some(size) = value.get(key).and_then(Value::as_u64) {
+                        conditions.push(format!("size{}{}MiB", op, size));
+                    }
+                }
+            },
+            "quality" => match value.as_str() {
+                Some(q) if q.chars().all(|c| c.is_alphanumeric()) => conditions.push(q.to_lowercase()),
+                _ => return Err("its quality requirement can't be translated".to_string()),
+            },
+            p if IGNORED_PLUGINS.contains(&p) => {},
+            p => return Err(format!("the {} plugin can't be translated", p)),
+        }
+    }
+
+    if urls.is_empty() {
+        return Err("it has no rss inputs".to_string());
+    }
+
+    let filters = if accept_all {
+        if conditions.is_empty() { Vec::new() } else { vec![conditions.join(" ")] }
+    }
+    else if accept.is_empty() {
+        return Err("it doesn't accept anything that can be translated".to_string());
+    }
+    else {
+        accept.into_iter()
+            .map(|mut words| {
+                words.extend(conditions.iter().cloned());
+                words.join(" ")
+            })
+            .collect()
+    };
+
+    Ok((urls, filters))
+}
+
+fn rss_url(value: &Value) -> Result<Url, String> {
+    let url = match *value {
+        Value::String(ref url) => url.as_str(),
+        _ => value.get("url").and_then(Value::as_str).ok_or("its rss input has no url")?,
+    };
+
+    Url::parse(url).map_err(|err| format!("invalid rss url {}: {}", url, err))
+}
+
+/// One word list per show, given either as a list of shows or as groups of shows.
+fn series_filters(value: &Value, notes: &mut Vec<String>, name: &str)
+    -> Result<Vec<Vec<String>>, String> {
+    let mut filters = Vec::new();
+
+    let groups: Vec<(Option<&Value>, &Value)> = match *value {
+        Value::Array(_) => vec![(None, value)],
+        Value::Object(ref groups) => groups.iter()
+            .filter(|&(group, _)| group != "settings")
+            .map(|(group, shows)| (value.get("settings").and_then(|s| s.get(group)), shows))
+            .collect(),
+        _ => return Err("its series list can't be read".to_string()),
+    };
+
+    for (group_settings, shows) in groups {
+        for show in as_list(shows) {
+            let (show_name, settings) = match *show {
+                Value::String(ref s) => (s.clone(), group_settings),
+                Value::Object(ref o) if o.len() == 1 => {
+                    let (n, s) = o.iter().next().unwrap();
+                    (n.clone(), Some(s).filter(|s| !s.is_null()).or(group_settings))
+                },
+                _ => return Err("its series list can't be read".to_string()),
+            };
+
+            match show_words(&show_name, settings) {
+                Some(words) => filters.push(words),
+                None => notes.push(format!("task {}: series {} has settings that can't be \
+                                            translated", name, show_name)),
+            }
+        }
+    }
+
+    Ok(filters)
+}
+
+/// The words of a show, or None if its settings need more than a simple quality.
+fn show_words(show: &str, settings: Option<&Value>) -> Option<Vec<String>> {
+    let mut words = rule_util::wildcard_words(show);
+
+    let settings = match settings.and_then(Value::as_object) {
+        Some(settings) => settings,
+        None => return Some(words),
+    };
+
+    for (key, value) in settings {
+        match (key.as_str(), value.as_str()) {
+            ("quality", Some(q)) if q.chars().all(|c| c.is_alphanumeric()) => {
+                words.push(q.to_lowercase());
+            },
+            _ => return None,
+        }
+    }
+
+    Some(words)
+}
+
+/// One word list per `accept` pattern. Anything but accept patterns is rejected, since leaving it
+/// out would accept more than FlexGet does.
+fn regexp_filters(value: &Value, notes: &mut Vec<String>, name: &str)
+    -> Result<Vec<Vec<String>>, String> {
+    let regexp = value.as_object().ok_or("its regexp settings can't be read")?;
+
+    let mut filters = Vec::new();
+    for (key, patterns) in regexp {
+        match key.as_str() {
+            "accept" => {},
+            "rest" if patterns.as_str() == Some("reject") => continue,
+            k => return Err(format!("regexp {} can't be translated", k)),
+        }
+
+        for pattern in as_list(patterns) {
+            let pattern = match *pattern {
+                Value::String(ref p) => p.clone(),
+                // patterns with options like `from` look at other fields than the title
+                _ => {
+                    notes.push(format!("task {}: regexp {} has options that can't be translated",
+                                       name, pattern));
+                    continue;
+                }
+            };
+
+            match rule_util::regex_filters(&pattern) {
+                Some(words) => filters.extend(words),
+                None => notes.push(format!("task {}: regexp {} can't be translated", name, pattern)),
+            }
+        }
+    }
+
+    Ok(filters)
+}
@@ -0,0 +1,73 @@
+use csv;
+
+use std::error::Error;
+use std::path::Path;
+use std::fs::{File, OpenOptions};
+
+use chrono::{DateTime, FixedOffset};
+
+use db_util;
+
+/// An item that was handed to the torrent command. `feed_url` is stored as a string rather than a
+/// `Url` since nothing needs to parse it back.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub feed_url: String,
+    pub title: String,
+    pub guid: Option<String>,
+    pub info_hash: Option<String>,
+    pub dispatched: DateTime<FixedOffset>,
+}
+
+pub const HISTORY_DB_FILENAME: &str = "history.csv";
+
+/// Opens the history db for appending, creating it if it is missing. Unlike the feed and alias dbs
+/// the history is never rewritten, only appended to.
+fn open_or_create_history_db(state_dir: &Path) -> Result<File, Box<Error>> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(db_util::db_path(state_dir, HISTORY_DB_FILENAME, "History"))?)
+}
+
+/// Read history db, or return no history if it does not exist.
+pub fn read_history_db(state_dir: &Path) -> Result<Vec<HistoryEntry>, Box<Error>> {
+    debug!("Reading history db.");
+
+    let mut history = Vec::new();
+
+    let db_file = match db_util::open_existing_db(state_dir, HISTORY_DB_FILENAME, "History")? {
+        Some(file) => file,
+        None => return Ok(history),
+    };
+
+    let mut reader = csv::Reader::from_reader(db_file);
+
+    for line in reader.deserialize() {
+        let entry: HistoryEntry = line?;
+        history.push(entry);
+    }
+
+    Ok(history)
+}
+
+/// Append entries to the history db, creating it if it does not exist.
+pub fn append_history_db(state_dir: &Path, entries: &[HistoryEntry]) -> Result<(), Box<Error>> {
+    debug!("Appending {} entries to history db.", entries.len());
+
+    let db_file = open_or_create_history_db(state_dir)?;
+    let is_empty = db_file.metadata()?.len() == 0;
+
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(is_empty)
+        .from_writer(db_file);
+
+    for entry in entries {
+        trace!("Serializing history entry {}", entry.title);
+        writer.serialize(entry)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
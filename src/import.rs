@@ -0,0 +1,218 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+
+use reqwest::Url;
+
+use alias::Alias;
+use autodl;
+use commands::{RTImport, RTImportAutodl, RTImportCommand, RTImportFile, RTImportLegacy};
+use feed_util::Feed;
+use flexget;
+use legacy;
+use memory_store::MemoryStore;
+use opml;
+use qbittorrent;
+use resolve;
+use store::Store;
+
+/// A feed read from another tool's data, before it is given an id.
+#[derive(Debug, Clone)]
+pub struct ImportedFeed {
+    pub url: Url,
+    pub filters: Vec<String>,
+    pub last_update: Option<DateTime<FixedOffset>>,
+    pub alias: Option<String>,
+}
+
+/// Everything read from an import source. `unsupported` describes things in the source that
+/// can't be expressed as feeds or aliases and were left out.
+#[derive(Debug, Clone, Default)]
+pub struct Imported {
+    pub feeds: Vec<ImportedFeed>,
+    pub aliases: Vec<Alias>,
+    pub unsupported: Vec<String>,
+}
+
+pub fn run_import(cmd: RTImport, store: &mut Store) {
+    match cmd.cmd {
+        RTImportCommand::Opml(opml_cmd) => import_file(opml_cmd, store, &opml::parse_opml),
+        RTImportCommand::Legacy(legacy_cmd) => import_legacy(legacy_cmd, store),
+        RTImportCommand::Flexget(flexget_cmd) => {
+            import_file(flexget_cmd, store, &flexget::parse_flexget)
+        },
+        RTImportCommand::Qbittorrent(qbittorrent_cmd) => {
+            import_file(qbittorrent_cmd, store, &qbittorrent::parse_qbittorrent)
+        },
+        RTImportCommand::Autodl(autodl_cmd) => import_autodl(autodl_cmd, store),
+    }
+}
+
+fn import_file(cmd: RTImportFile, store: &mut Store,
+               parse: &Fn(&str) -> Result<Imported, Box<Error>>) {
+    let result = read_file(&cmd.file).and_then(|contents| parse(&contents));
+
+    match result {
+        Ok(imported) => import(imported, store, cmd.dry_run),
+        Err(err) => error!("Could not read {}: {}. Not importing anything.", cmd.file, err),
+    }
+}
+
+fn import_autodl(cmd: RTImportAutodl, store: &mut Store) {
+    let url = match resolve::resolve_url(store, &cmd.feed) {
+        Ok(url) => url,
+        Err(err) => {
+            error!("Not importing anything because the feed could not be understood: {}", err);
+            return;
+        }
+    };
+
+    let result = read_file(&cmd.file).and_then(|contents| autodl::parse_autodl(&contents, &url));
+
+    match result {
+        Ok(imported) => import(imported, store, cmd.dry_run),
+        Err(err) => error!("Could not read {}: {}. Not importing anything.", cmd.file, err),
+    }
+}
+
+fn import_legacy(cmd: RTImportLegacy, store: &mut Store) {
+    legacy::import_legacy(Path::new(&cmd.dir), store, cmd.dry_run);
+}
+
+pub fn read_file(path: &str) -> Result<String, Box<Error>> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// What an import did, as descriptions of the aliases and feeds.
+#[derive(Debug, Default)]
+struct ImportReport {
+    added: Vec<String>,
+    skipped: Vec<String>,
+}
+
+/// Add the imported aliases and feeds, skipping ones that already exist, and print what was done.
+/// With `dry_run` the changes are made to an in-memory copy of the store instead.
+pub fn import(imported: Imported, store: &mut Store, dry_run: bool) {
+    let mut report = ImportReport::default();
+
+    let result = if dry_run {
+        MemoryStore::load(store).and_then(|mut memory| apply(&imported, &mut memory, &mut report))
+    }
+    else {
+        store.transaction(&mut |tx| apply(&imported, tx, &mut report))
+    };
+
+    if let Err(err) = result {
+        error!("Could not import: {}. Nothing was imported.", err);
+        return;
+    }
+
+    let verb = if dry_run { "Would add" } else { "Added" };
+    for line in &report.added {
+        println!("{} {}", verb, line);
+    }
+    for line in &report.skipped {
+        println!("Skipped {}", line);
+    }
+    for line in &imported.unsupported {
+        println!("Not imported: {}", line);
+    }
+
+    println!("{} {} and skipped {} aliases and feeds. {} entries could not be imported.",
+             verb, report.added.len(), report.skipped.len(), imported.unsupported.len());
+}
+
+fn apply(imported: &Imported, store: &mut Store, report: &mut ImportReport) -> Result<(), Box<Error>> {
+    // aliases first, so feeds can follow them
+    for alias in &imported.aliases {
+        match store.get_alias(&alias.name)? {
+            Some(ref existing) if existing.url == alias.url => {
+                report.skipped.push(format!("alias {}: it already exists", alias.name));
+            },
+            Some(_) => {
+                report.skipped.push(format!("alias {}: an alias with that name and a different \
+                                             url already exists", alias.name));
+            },
+            None => {
+                store.insert_alias(alias.clone())?;
+                report.added.push(format!("alias {}", alias.name));
+            }
+        }
+    }
+
+    // same duplicate check as `add`
+    let mut feeds = store.list_feeds()?;
+    for imported_feed in &imported.feeds {
+        let mut feed = Feed::new(imported_feed.url.clone(), imported_feed.filters.clone(), &feeds);
+        feed.last_update = imported_feed.last_update;
+        feed.alias = imported_feed.alias.clone();
+
+        if let Some(existing) = feeds.get(&feed) {
+            report.skipped.push(format!("feed {}: it already exists", existing));
+            continue;
+        }
+
+        store.insert_feed(feed.clone())?;
+        report.added.push(format!("feed {}", feed));
+        feeds.insert(feed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use feed_util::Feeds;
+
+    fn imported_feed(url: &str, filters: &[&str]) -> ImportedFeed {
+        ImportedFeed {
+            url: Url::parse(url).unwrap(),
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+            last_update: None,
+            alias: None,
+        }
+    }
+
+    #[test]
+    fn skips_feeds_and_aliases_that_exist() {
+        let mut store = MemoryStore::default();
+        let url = Url::parse("https://t.example/rss").unwrap();
+        store.insert_feed(Feed::new(url.clone(), vec!["Some Show".to_string()], &Feeds::new()))
+            .unwrap();
+        store.insert_alias(Alias { name: "tracker".to_string(), url: url.clone() }).unwrap();
+
+        let other_url = Url::parse("https://t.example/other").unwrap();
+        let imported = Imported {
+            feeds: vec![
+                // the same filters after normalizing, like `add` compares them
+                imported_feed("https://t.example/rss", &["show  some"]),
+                imported_feed("https://t.example/rss", &["other show"]),
+                // repeated within the import itself
+                imported_feed("https://t.example/rss", &["Other Show"]),
+                imported_feed("https://t.example/other", &[]),
+            ],
+            aliases: vec![
+                Alias { name: "tracker".to_string(), url: url.clone() },
+                Alias { name: "tracker".to_string(), url: other_url.clone() },
+                Alias { name: "other".to_string(), url: other_url },
+            ],
+            unsupported: Vec::new(),
+        };
+
+        let mut report = ImportReport::default();
+        apply(&imported, &mut store, &mut report).unwrap();
+
+        assert_eq!(report.added.len(), 3, "{:?}", report.added);
+        assert_eq!(report.skipped.len(), 4, "{:?}", report.skipped);
+        assert_eq!(store.list_feeds().unwrap().len(), 3);
+        assert_eq!(store.list_aliases().unwrap().len(), 2);
+        assert_eq!(store.get_alias("tracker").unwrap().unwrap().url, url);
+    }
+}
@@ -0,0 +1,159 @@
+use chrono::{DateTime, FixedOffset};
+
+use atom_syndication as atom;
+use rss;
+
+use torznab;
+use tracker_ext;
+
+/// A file attached to an item, e.g. the .torrent file. `length` is in bytes.
+#[derive(Debug, Clone)]
+pub struct Enclosure {
+    pub url: String,
+    pub length: Option<u64>,
+    pub mime_type: Option<String>,
+}
+
+/// A single entry of a feed, independent of which format the feed was in. `size` is in bytes and
+/// `info_hash` is lowercase hex. `size`, `seeders`, `info_hash`, `magnet` and `imdb_id` are only
+/// known if the feed provides them, e.g. via torznab attributes or a tracker's own namespace.
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub title: String,
+    pub links: Vec<String>,
+    pub enclosures: Vec<Enclosure>,
+    pub id: Option<String>,
+    pub published: Option<DateTime<FixedOffset>>,
+    pub updated: Option<DateTime<FixedOffset>>,
+    pub categories: Vec<String>,
+    pub size: Option<u64>,
+    pub seeders: Option<u64>,
+    pub info_hash: Option<String>,
+    pub magnet: Option<String>,
+    pub imdb_id: Option<String>,
+}
+
+impl Item {
+    /// The date used to compare against a feed's `last_update`.
+    pub fn date(&self) -> Option<DateTime<FixedOffset>> {
+        self.published.or(self.updated)
+    }
+
+    /// The url that should be handed to the torrent command. Enclosures are preferred since that's
+    /// where most trackers put the torrent file, and the item link is often just a details page.
+    pub fn download_url(&self) -> Option<&str> {
+        self.enclosures.first().map(|e| e.url.as_str())
+            .or_else(|| self.magnet.as_ref().map(|m| m.as_str()))
+            .or_else(|| self.links.first().map(|l| l.as_str()))
+    }
+
+    /// Fill in `info_hash` from a magnet link if the feed didn't give us one directly.
+    pub fn infer_info_hash(&mut self) {
+        if self.info_hash.is_some() {
+            return;
+        }
+
+        let hash = self.magnet.iter()
+            .chain(self.links.iter())
+            .chain(self.enclosures.iter().map(|e| &e.url))
+            .filter_map(|url| tracker_ext::magnet_info_hash(url))
+            .next();
+        self.info_hash = hash;
+    }
+
+    pub fn from_rss(item: &rss::Item) -> Item {
+        let mut published = item.pub_date().and_then(parse_rfc2822);
+
+        // RSS 1.0 feeds don't have pubDate and use dc:date instead
+        if published.is_none() {
+            published = item.dublin_core_ext()
+                .and_then(|dc| dc.dates().first())
+                .and_then(|d| parse_rfc3339(d));
+        }
+
+        let mut new_item = Item {
+            title: item.title().unwrap_or("").to_string(),
+            links: item.link().map(|l| vec![l.to_string()]).unwrap_or_default(),
+            enclosures: item.enclosure().map(|e| vec![Enclosure {
+                url: e.url().to_string(),
+                length: e.length().parse().ok(),
+                mime_type: Some(e.mime_type().to_string()),
+            }]).unwrap_or_default(),
+            id: item.guid().map(|g| g.value().to_string()),
+            published: published,
+            updated: None,
+            categories: item.categories().iter().map(|c| c.name().to_string()).collect(),
+            size: None,
+            seeders: None,
+            info_hash: None,
+            magnet: None,
+            imdb_id: None,
+        };
+
+        torznab::apply_attrs(&mut new_item, item.extensions());
+        tracker_ext::apply_extensions(&mut new_item, item.extensions());
+        if new_item.size.is_none() {
+            new_item.size = new_item.enclosures.first().and_then(|e| e.length);
+        }
+        new_item.infer_info_hash();
+
+        new_item
+    }
+
+    pub fn from_atom(entry: &atom::Entry) -> Item {
+        let mut links = Vec::new();
+        let mut enclosures = Vec::new();
+        for link in entry.links() {
+            if link.rel() == "enclosure" {
+                enclosures.push(Enclosure {
+                    url: link.href().to_string(),
+                    length: link.length().and_then(|l| l.parse().ok()),
+                    mime_type: link.mime_type().map(|m| m.to_string()),
+                });
+            }
+            else {
+                links.push(link.href().to_string());
+            }
+        }
+
+        let size = enclosures.first().and_then(|e| e.length);
+
+        let mut new_item = Item {
+            title: entry.title().to_string(),
+            links: links,
+            enclosures: enclosures,
+            id: Some(entry.id().to_string()),
+            published: entry.published().and_then(parse_rfc3339),
+            updated: parse_rfc3339(entry.updated()),
+            categories: entry.categories().iter().map(|c| c.term().to_string()).collect(),
+            size: size,
+            seeders: None,
+            info_hash: None,
+            magnet: None,
+            imdb_id: None,
+        };
+
+        new_item.infer_info_hash();
+        new_item
+    }
+}
+
+pub fn parse_rfc2822(date: &str) -> Option<DateTime<FixedOffset>> {
+    match DateTime::parse_from_rfc2822(date.trim()) {
+        Ok(d) => Some(d),
+        Err(err) => {
+            debug!("Could not parse date {}: {}", date, err);
+            None
+        }
+    }
+}
+
+pub fn parse_rfc3339(date: &str) -> Option<DateTime<FixedOffset>> {
+    match DateTime::parse_from_rfc3339(date.trim()) {
+        Ok(d) => Some(d),
+        Err(err) => {
+            debug!("Could not parse date {}: {}", date, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+
+use serde_json;
+
+use item::{self, Item, Enclosure};
+
+// Only the parts of https://jsonfeed.org/version/1.1 that we use. Everything else is ignored by
+// serde.
+
+const VERSION_PREFIX: &str = "https://jsonfeed.org/version/";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonFeed {
+    pub version: String,
+    #[serde(default)]
+    pub items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonFeedItem {
+    // The spec says this is a string but some feeds use numbers
+    pub id: serde_json::Value,
+    pub url: Option<String>,
+    pub external_url: Option<String>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonFeedAttachment {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub size_in_bytes: Option<u64>,
+}
+
+impl JsonFeedItem {
+    pub fn to_item(self) -> Item {
+        let id = match self.id {
+            serde_json::Value::String(s) => Some(s),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        };
+
+        // title is optional in json feed, e.g. for microblog posts
+        let title = self.title.or(self.summary).or_else(|| id.clone()).unwrap_or_default();
+
+        let mut links = Vec::new();
+        links.extend(self.url);
+        links.extend(self.external_url);
+
+        let enclosures: Vec<Enclosure> = self.attachments.into_iter().map(|a| Enclosure {
+            url: a.url,
+            length: a.size_in_bytes,
+            mime_type: a.mime_type,
+        }).collect();
+        let size = enclosures.first().and_then(|e| e.length);
+
+        let mut new_item = Item {
+            title: title,
+            links: links,
+            enclosures: enclosures,
+            id: id,
+            published: self.date_published.as_ref().and_then(|d| item::parse_rfc3339(d)),
+            updated: self.date_modified.as_ref().and_then(|d| item::parse_rfc3339(d)),
+            categories: self.tags,
+            size: size,
+            seeders: None,
+            info_hash: None,
+            magnet: None,
+            imdb_id: None,
+        };
+
+        new_item.infer_info_hash();
+        new_item
+    }
+}
+
+pub fn parse_json_feed(body: &str) -> Result<Vec<Item>, Box<Error>> {
+    let feed: JsonFeed = serde_json::from_str(body)?;
+
+    if !feed.version.starts_with(VERSION_PREFIX) {
+        return Err(Box::new(io::Error::new(ErrorKind::InvalidData,
+                                           format!("Unknown JSON Feed version {}", feed.version))));
+    }
+
+    Ok(feed.items.into_iter().map(JsonFeedItem::to_item).collect())
+}
@@ -0,0 +1,237 @@
+use csv;
+
+use std::error::Error;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use chrono::{FixedOffset, TimeZone};
+
+use reqwest::Url;
+
+use alias::{Alias, AliasRecord};
+use import::{self, Imported, ImportedFeed};
+use store::Store;
+
+// The old script didn't have fixed file names, so try the likely ones.
+const LEGACY_FEED_FILES: &[&str] = &["feeds.csv", "database.csv", "db.csv", "feeds"];
+const LEGACY_ALIAS_FILES: &[&str] = &["aliases.csv", "alias.csv", "aliases"];
+
+/// A row of the old feeds db: `url,lastupdate,filter` where `lastupdate` is a unix timestamp (0 for
+/// never) and `filter` is a single filter string. `lastupdate` is read as a string since Python
+/// may have written it as a float. Rows without a filter may be missing the column entirely.
+#[derive(Debug, Deserialize)]
+struct LegacyEntry {
+    url: String,
+    lastupdate: String,
+    #[serde(default)]
+    filter: Option<String>,
+}
+
+fn find_file(dir: &Path, names: &[&str]) -> Option<PathBuf> {
+    names.iter().map(|name| dir.join(name)).find(|path| path.is_file())
+}
+
+/// Rows of a csv file that may or may not have a header row starting with `first_header`.
+fn read_rows(path: &Path, first_header: &str) -> Result<Vec<(u64, csv::StringRecord)>, Box<Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        if i == 0 && record.get(0) == Some(first_header) {
+            continue;
+        }
+        rows.push((i as u64 + 1, record));
+    }
+
+    Ok(rows)
+}
+
+fn parse_timestamp(s: &str) -> Result<Option<i64>, Box<Error>> {
+    let s = s.trim();
+    let secs = match s.parse::<i64>() {
+        Ok(secs) => secs,
+        Err(_) => s.parse::<f64>()? as i64,
+    };
+
+    Ok(if secs == 0 { None } else { Some(secs) })
+}
+
+/// Read the old script's feeds and aliases from `dir`.
+pub fn read_legacy_dir(dir: &Path) -> Result<Imported, Box<Error>> {
+    let mut imported = Imported::default();
+
+    let feeds_path = find_file(dir, LEGACY_FEED_FILES);
+    let aliases_path = find_file(dir, LEGACY_ALIAS_FILES);
+    if feeds_path.is_none() && aliases_path.is_none() {
+        return Err(Box::new(io::Error::new(ErrorKind::NotFound,
+            format!("No legacy feeds ({}) or aliases ({}) found in {}", LEGACY_FEED_FILES.join(", "),
+                    LEGACY_ALIAS_FILES.join(", "), dir.to_string_lossy()))));
+    }
+
+    if let Some(ref path) = aliases_path {
+        info!("Reading legacy aliases from {}", path.to_string_lossy());
+        for (line, record) in read_rows(path, "name")? {
+            match legacy_alias(&record) {
+                Ok(alias) => imported.aliases.push(alias),
+                Err(err) => imported.unsupported.push(format!("{} line {}: {}",
+                                                              path.to_string_lossy(), line, err)),
+            }
+        }
+    }
+
+    if let Some(ref path) = feeds_path {
+        info!("Reading legacy feeds from {}", path.to_string_lossy());
+        for (line, record) in read_rows(path, "url")? {
+            match legacy_feed(&record) {
+                Ok(feed) => imported.feeds.push(feed),
+                Err(err) => imported.unsupported.push(format!("{} line {}: {}",
+                                                              path.to_string_lossy(), line, err)),
+            }
+        }
+    }
+
+    Ok(imported)
+}
+
+fn legacy_alias(record: &csv::StringRecord) -> Result<Alias, Box<Error>> {
+    let record: AliasRecord = record.deserialize(None)?;
+    record.to_alias()
+}
+
+fn legacy_feed(record: &csv::StringRecord) -> Result<ImportedFeed, Box<Error>> {
+    let entry: LegacyEntry = record.deserialize(None)?;
+
+    let last_update = parse_timestamp(&entry.lastupdate)?
+        .map(|secs| FixedOffset::east(0).timestamp(secs, 0));
+
+    // the old filter was a single string of words, which is exactly one filter now
+    let filters = match entry.filter {
+        Some(ref f) if !f.trim().is_empty() => vec![f.trim().to_string()],
+        _ => Vec::new(),
+    };
+
+    Ok(ImportedFeed {
+        url: Url::parse(&entry.url)?,
+        filters: filters,
+        last_update: last_update,
+        alias: None,
+    })
+}
+
+/// Back up the current databases, then import the legacy files in `dir`.
+pub fn import_legacy(dir: &Path, store: &mut Store, dry_run: bool) {
+    let imported = match read_legacy_dir(dir) {
+        Ok(imported) => imported,
+        Err(err) => {
+            error!("Could not read legacy databases: {}. Not importing anything.", err);
+            return;
+        }
+    };
+
+    if !dry_run {
+        match store.backup("pre-import") {
+            Ok(paths) => {
+                for path in paths {
+                    println!("Backed up {}", path.to_string_lossy());
+                }
+            },
+            Err(err) => {
+                error!("Could not back up the databases: {}. Not importing anything.", err);
+                return;
+            }
+        }
+    }
+
+    import::import(imported, store, dry_run);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::DateTime;
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("test/legacy").join(name)
+    }
+
+    fn date(secs: i64) -> Option<DateTime<FixedOffset>> {
+        Some(FixedOffset::east(0).timestamp(secs, 0))
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1539678600").unwrap(), Some(1539678600));
+        assert_eq!(parse_timestamp(" 1539678600 ").unwrap(), Some(1539678600));
+        // Python's time.time() was sometimes written as is
+        assert_eq!(parse_timestamp("1539678600.75").unwrap(), Some(1539678600));
+        assert_eq!(parse_timestamp("1539678600.0").unwrap(), Some(1539678600));
+        assert_eq!(parse_timestamp("1.5396786e9").unwrap(), Some(1539678600));
+
+        // 0 meant never updated
+        assert_eq!(parse_timestamp("0").unwrap(), None);
+        assert_eq!(parse_timestamp("0.0").unwrap(), None);
+
+        assert!(parse_timestamp("").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn reads_files_with_headers() {
+        let imported = read_legacy_dir(&fixture_dir("headers")).unwrap();
+
+        let names: Vec<&str> = imported.aliases.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["tracker", "other"]);
+        assert_eq!(imported.aliases[0].url.as_str(), "https://t.example/rss?passkey=abc");
+
+        let feeds = &imported.feeds;
+        assert_eq!(feeds.len(), 4, "{:?}", imported.unsupported);
+
+        assert_eq!(feeds[0].url.as_str(), "https://t.example/rss?passkey=abc");
+        assert_eq!(feeds[0].filters, vec!["Some Show 720p"]);
+        assert_eq!(feeds[0].last_update, date(1539678600));
+        assert_eq!(feeds[0].alias, None);
+
+        // filters are trimmed, and float timestamps truncated
+        assert_eq!(feeds[1].filters, vec!["other show"]);
+        assert_eq!(feeds[1].last_update, date(1539678600));
+
+        // an empty or missing filter matched everything, like no filters do now
+        assert_eq!(feeds[2].url.as_str(), "https://other.example/feed.xml");
+        assert!(feeds[2].filters.is_empty());
+        assert_eq!(feeds[2].last_update, None);
+        assert_eq!(feeds[3].url.as_str(), "https://other.example/all.xml");
+        assert!(feeds[3].filters.is_empty());
+
+        // the rows with a bad url and a bad timestamp, by line number including the header
+        assert_eq!(imported.unsupported.len(), 2, "{:?}", imported.unsupported);
+        assert!(imported.unsupported[0].contains("feeds.csv line 6"), "{:?}", imported.unsupported);
+        assert!(imported.unsupported[1].contains("feeds.csv line 7"), "{:?}", imported.unsupported);
+    }
+
+    #[test]
+    fn reads_files_without_headers() {
+        let imported = read_legacy_dir(&fixture_dir("headerless")).unwrap();
+
+        assert_eq!(imported.aliases.len(), 1);
+        assert_eq!(imported.aliases[0].name, "tracker");
+        assert_eq!(imported.unsupported.len(), 1, "{:?}", imported.unsupported);
+        assert!(imported.unsupported[0].contains("alias.csv line 2"), "{:?}", imported.unsupported);
+
+        let feeds = &imported.feeds;
+        assert_eq!(feeds.len(), 2);
+        assert_eq!(feeds[0].filters, vec!["Some Show 1080p"]);
+        assert_eq!(feeds[0].last_update, date(1539678600));
+        assert!(feeds[1].filters.is_empty());
+        assert_eq!(feeds[1].last_update, None);
+    }
+
+    #[test]
+    fn needs_feeds_or_aliases() {
+        assert!(read_legacy_dir(&fixture_dir("missing")).is_err());
+    }
+}
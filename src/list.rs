@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset};
+
+use reqwest::Url;
+
+use alias::Aliases;
+use commands::RTList;
+use feed_util::Feed;
+use output::{self, OutputFormat};
+use redact::{self, RedactedUrl};
+use store::Store;
+
+/// What `list` lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    Feeds,
+    Aliases,
+}
+
+impl FromStr for ListKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ListKind, String> {
+        match s {
+            "feeds" => Ok(ListKind::Feeds),
+            "aliases" => Ok(ListKind::Aliases),
+            _ => Err(format!("Unknown list {}, expected feeds or aliases", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Id,
+    Url,
+    Alias,
+    LastUpdate,
+    Failures,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s {
+            "id" => Ok(SortKey::Id),
+            "url" => Ok(SortKey::Url),
+            "alias" => Ok(SortKey::Alias),
+            "last-update" => Ok(SortKey::LastUpdate),
+            "failures" => Ok(SortKey::Failures),
+            _ => Err(format!("Unknown sort key {}, expected id, url, alias, last-update or failures",
+                             s)),
+        }
+    }
+}
+
+const FEED_HEADERS: &[&str] = &["id", "alias", "url", "filters", "last_update", "paused", "failures"];
+const ALIAS_HEADERS: &[&str] = &["name", "url"];
+
+/// A feed as it is printed. The url and the alias parameters are already redacted.
+#[derive(Serialize)]
+struct FeedListing {
+    id: String,
+    alias: Option<String>,
+    url: String,
+    filters: Vec<String>,
+    last_update: Option<String>,
+    paused: bool,
+    failures: u32,
+    // the rfc3339 strings don't sort correctly across time zones
+    #[serde(skip)]
+    updated: Option<DateTime<FixedOffset>>,
+}
+
+impl FeedListing {
+    fn new(feed: &Feed, alias: Option<String>) -> FeedListing {
+        FeedListing {
+            id: feed.id.clone(),
+            alias: alias.map(|a| redact::redact_alias_ref(&a)),
+            url: RedactedUrl(&feed.url).to_string(),
+            filters: feed.filters.clone(),
+            last_update: feed.last_update.map(|d| d.to_rfc3339()),
+            paused: feed.paused,
+            failures: feed.failures,
+            updated: feed.last_update,
+        }
+    }
+
+    fn to_row(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.alias.clone().unwrap_or_default(),
+            self.url.clone(),
+            self.filters.join(", "),
+            self.last_update.clone().unwrap_or_else(|| "never".to_string()),
+            if self.paused { "yes" } else { "no" }.to_string(),
+            self.failures.to_string(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct AliasListing {
+    name: String,
+    url: String,
+}
+
+pub fn list(cmd: RTList, store: &mut Store) {
+    let result = match cmd.kind {
+        ListKind::Feeds => list_feeds(&cmd, store),
+        ListKind::Aliases => list_aliases(store, cmd.format, cmd.sort, cmd.reverse),
+    };
+
+    if let Err(err) = result {
+        error!("Could not list {:?}: {}", cmd.kind, err);
+    }
+}
+
+/// Map each aliased url to its alias. If several aliases point to the same url, the first one in
+/// alphabetical order is used.
+pub fn reverse_aliases(aliases: &Aliases) -> HashMap<Url, String> {
+    let mut names: Vec<&String> = aliases.keys().collect();
+    names.sort();
+
+    let mut reversed = HashMap::new();
+    for name in names {
+        reversed.entry(aliases[name].url.clone()).or_insert_with(|| name.clone());
+    }
+
+    reversed
+}
+
+fn list_feeds(cmd: &RTList, store: &mut Store) -> Result<(), Box<Error>> {
+    let feeds = store.list_feeds()?;
+    let aliases = reverse_aliases(&store.list_aliases()?);
+
+    let mut listings: Vec<FeedListing> = feeds.iter()
+        .map(|feed| {
+            let alias = feed.alias.clone().or_else(|| aliases.get(&feed.url).cloned());
+            FeedListing::new(feed, alias)
+        })
+        .collect();
+
+    // the url and alias are compared redacted so that sorting doesn't leak anything about the
+    // secrets
+    listings.sort_by(|a, b| match cmd.sort {
+        SortKey::Id => a.id.cmp(&b.id),
+        SortKey::Url => a.url.cmp(&b.url).then_with(|| a.id.cmp(&b.id)),
+        SortKey::Alias => a.alias.cmp(&b.alias).then_with(|| a.id.cmp(&b.id)),
+        SortKey::LastUpdate => a.updated.cmp(&b.updated).then_with(|| a.id.cmp(&b.id)),
+        SortKey::Failures => a.failures.cmp(&b.failures).then_with(|| a.id.cmp(&b.id)),
+    });
+
+    if cmd.reverse {
+        listings.reverse();
+    }
+
+    match cmd.format {
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = listings.iter().map(|l| l.to_row()).collect();
+            output::print_table(FEED_HEADERS, &rows);
+            Ok(())
+        },
+        OutputFormat::Csv => {
+            let rows: Vec<Vec<String>> = listings.iter().map(|l| l.to_row()).collect();
+            output::print_csv(FEED_HEADERS, &rows)
+        },
+        OutputFormat::Json => output::print_json(&listings),
+    }
+}
+
+/// Also used by `alias list`.
+pub fn list_aliases(store: &mut Store, format: OutputFormat, sort: SortKey, reverse: bool)
+    -> Result<(), Box<Error>> {
+    let aliases = store.list_aliases()?;
+
+    let mut listings: Vec<AliasListing> = aliases.values()
+        .map(|alias| AliasListing {
+            name: alias.name.clone(),
+            url: RedactedUrl(&alias.url).to_string(),
+        })
+        .collect();
+
+    match sort {
+        SortKey::Url => listings.sort_by(|a, b| a.url.cmp(&b.url).then_with(|| a.name.cmp(&b.name))),
+        _ => listings.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+
+    if reverse {
+        listings.reverse();
+    }
+
+    let rows: Vec<Vec<String>> = listings.iter()
+        .map(|l| vec![l.name.clone(), l.url.clone()])
+        .collect();
+
+    match format {
+        OutputFormat::Table => {
+            output::print_table(ALIAS_HEADERS, &rows);
+            Ok(())
+        },
+        OutputFormat::Csv => output::print_csv(ALIAS_HEADERS, &rows),
+        OutputFormat::Json => output::print_json(&listings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    use alias::Alias;
+    use feed_util::Feeds;
+    use redact::SECRETS_LOCK;
+
+    #[test]
+    fn redacts_urls_and_alias_params() {
+        let _lock = SECRETS_LOCK.lock().unwrap();
+
+        let url = Url::parse("https://t.example/rss?passkey=abc123&q=show").unwrap();
+        let mut feed = Feed::new(url, vec!["720p".to_string()], &Feeds::new());
+        feed.alias = Some("tracker:passkey=abc123,q=show".to_string());
+        let listing = FeedListing::new(&feed, feed.alias.clone());
+
+        // table and csv
+        let row = listing.to_row();
+        assert_eq!(row[1], "tracker:passkey=REDACTED,q=show");
+        assert_eq!(row[2], "https://t.example/rss?passkey=REDACTED&q=show");
+        assert!(row.iter().all(|cell| !cell.contains("abc123")), "{:?}", row);
+
+        let json = serde_json::to_string(&listing).unwrap();
+        assert!(json.contains("\"alias\":\"tracker:passkey=REDACTED,q=show\""), "{}", json);
+        assert!(!json.contains("abc123"), "{}", json);
+    }
+
+    #[test]
+    fn reverse_aliases_uses_the_first_name() {
+        let url = Url::parse("https://t.example/rss").unwrap();
+        let mut aliases = Aliases::new();
+        for name in &["tracker", "another", "zzz"] {
+            aliases.insert(name.to_string(), Alias { name: name.to_string(), url: url.clone() });
+        }
+
+        let reversed = reverse_aliases(&aliases);
+        assert_eq!(reversed.len(), 1);
+        assert_eq!(reversed[&url], "another");
+    }
+}
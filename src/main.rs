@@ -1,3 +1,15 @@
+// The code is written for Rust 2015 and the toolchains of its time. These lints from newer
+// toolchains either ask for `dyn` and field init shorthand everywhere or suggest std functions
+// that didn't exist yet, so they are allowed rather than half the code being rewritten.
+#![allow(unknown_lints, bare_trait_objects)]
+#![allow(clippy::redundant_field_names, clippy::needless_late_init,
+         clippy::needless_borrowed_reference, clippy::wrong_self_convention,
+         clippy::type_complexity, clippy::derivable_impls, clippy::unnecessary_map_or,
+         clippy::io_other_error, clippy::option_as_ref_deref, clippy::manual_inspect,
+         clippy::manual_split_once, clippy::manual_strip, clippy::legacy_numeric_constants)]
+
+extern crate atom_syndication;
+
 extern crate csv;
 
 extern crate chrono;
@@ -6,17 +18,39 @@ extern crate fern;
 #[macro_use]
 extern crate log;
 
+#[macro_use]
+extern crate lazy_static;
+
 extern crate reqwest;
 
+extern crate quick_xml;
+
+extern crate rss;
+
+extern crate rusqlite;
+
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
 
 #[macro_use]
 extern crate structopt;
 
 extern crate toml;
 
+#[cfg(test)]
+extern crate quickcheck;
+#[cfg(test)]
+extern crate tempdir;
+
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
 
 use structopt::StructOpt;
 
@@ -24,53 +58,188 @@ mod config;
 use config::RTConfig;
 
 mod commands;
-use commands::{RTArgs, RTCommand};
+use commands::{RTArgs, RTCommand, RTUpdate};
 
 mod add; use add::add_feed;
-mod alias; use alias::add_alias;
+mod alias; use alias::run_alias;
 mod update; use update::run_update;
 mod delete; use delete::delete_feed;
+mod edit; use edit::edit_feed;
+mod pause; use pause::{pause_feeds, resume_feeds};
+mod show; use show::show_feed;
+mod preview; use preview::preview_feed;
+mod list; use list::list;
+mod status; use status::show_status;
+mod import; use import::run_import;
+mod export; use export::run_export;
+mod config_cmd; use config_cmd::run_config;
+mod db; use db::run_db;
 mod alias_util;
+mod autodl;
+mod csv_store;
 mod feed_util;
+mod db_util;
+mod feed_parse;
+mod filter;
+mod flexget;
+mod history_util;
+mod item;
+mod json_feed;
+mod legacy;
+mod memory_store;
+mod opml;
+mod output; use output::OutputFormat;
+mod qbittorrent;
+mod redact;
+mod resolve;
+mod rule_util;
+mod run_util;
+mod sqlite_store;
+mod store;
+mod torznab;
+mod tracker_ext;
+
+const LOG_FILENAME: &str = "rss-torrent.log";
+
+/// Where the log file is in `state_dir`, which is only known once the config is read. Until then
+/// the messages are kept, so that problems with the config end up in the log file too.
+enum LogFile {
+    Pending(Vec<String>),
+    Open(File),
+    Disabled,
+}
+
+lazy_static! {
+    static ref LOG_FILE: Mutex<LogFile> = Mutex::new(LogFile::Pending(Vec::new()));
+}
 
 fn main() {
     let args = RTArgs::from_args();
+    redact::set_show_secrets(args.show_secrets);
     setup_logger(level_from_verbosity(args.verbosity));
 
-    let config = RTConfig::new(args.config);
+    let (config, report) = RTConfig::load(args.config);
+    open_log_file(&config.state_dir);
+    redact::set_extra_keys(&config.redact_keys);
+
+    // config commands work without a usable database, or config
+    if let Some(RTCommand::Config(ref config_cmd)) = args.cmd {
+        if !run_config(config_cmd, &config, &report) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(ref err) = report.invalid {
+        error!("Invalid config: {}. See `rss-torrent config show` for where the values come from.",
+               err);
+        process::exit(1);
+    }
+
+    let mut store = match store::open_store(&config) {
+        Ok(s) => s,
+        Err(err) => {
+            error!("Could not open {} database: {}", config.storage, err);
+            return;
+        }
+    };
+
+    // only `status` and `config` report problems through the exit code so far
+    let mut healthy = true;
 
     if let Some(cmd) = args.cmd {
         match cmd {
-            RTCommand::Add(add) => add_feed(add, &config),
-            RTCommand::Alias(alias) => add_alias(alias, &config),
-            RTCommand::Update => run_update(&config),
-            RTCommand::Delete(delete) => delete_feed(delete, &config),
+            RTCommand::Add(add) => add_feed(add, &mut *store),
+            RTCommand::Alias(alias) => run_alias(alias, &mut *store),
+            RTCommand::Update(update) => run_update(&update, &config, &mut *store),
+            RTCommand::Delete(delete) => delete_feed(delete, &mut *store),
+            RTCommand::Edit(edit) => edit_feed(edit, &mut *store),
+            RTCommand::Pause(pause) => pause_feeds(pause, &mut *store),
+            RTCommand::Resume(resume) => resume_feeds(resume, &mut *store),
+            RTCommand::Show(show) => show_feed(show, &mut *store),
+            RTCommand::Preview(preview) => preview_feed(preview, &mut *store),
+            RTCommand::List(list_cmd) => list(list_cmd, &mut *store),
+            RTCommand::Status(status) => healthy = show_status(status, &mut *store),
+            RTCommand::Import(import) => run_import(import, &mut *store),
+            RTCommand::Export(export) => run_export(export, &mut *store),
+            RTCommand::Config(_) => unreachable!(),
+            RTCommand::Db(db) => run_db(db, &config),
         }
     }
 
     if args.update {
-        run_update(&config);
+        let update = RTUpdate { dry_run: false, format: OutputFormat::Table };
+        run_update(&update, &config, &mut *store);
+    }
+
+    if !healthy {
+        process::exit(1);
     }
 }
 
+/// Log to stdout and to a log file in the configured state dir. The logger is set up before the
+/// config is read, so messages are kept in memory until `open_log_file` knows where to write them.
 fn setup_logger(log_level: log::LevelFilter) {
     let logger = fern::Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
                 "[{}] {}",
                 record.level(),
-                message
+                redact::redact_str(&message.to_string())
             ))
         })
         .level(log_level)
         .chain(std::io::stdout())
-        .apply();
-
-    if !logger.is_ok() {
+        .chain(fern::Output::call(|record| {
+            // the format above only applies to stdout
+            let line = redact::redact_str(&record.args().to_string());
+            if let Ok(mut log_file) = LOG_FILE.lock() {
+                match *log_file {
+                    LogFile::Pending(ref mut lines) => lines.push(line),
+                    LogFile::Open(ref mut file) => { let _ = writeln!(file, "{}", line); },
+                    LogFile::Disabled => {},
+                }
+            }
+        }));
+
+    let logger = logger.apply();
+
+    if logger.is_err() {
         println!("Logging is disabled.");
     }
 }
 
+/// Write the log to `LOG_FILENAME` in `state_dir` from now on, starting with the messages logged
+/// so far.
+fn open_log_file(state_dir: &Path) {
+    let result = fs::create_dir_all(state_dir).and_then(|_| {
+        OpenOptions::new().create(true).append(true).open(state_dir.join(LOG_FILENAME))
+    });
+
+    let mut log_file = match LOG_FILE.lock() {
+        Ok(log_file) => log_file,
+        Err(_) => return,
+    };
+    let pending = match *log_file {
+        LogFile::Pending(ref mut lines) => lines.split_off(0),
+        _ => Vec::new(),
+    };
+
+    match result {
+        Ok(mut file) => {
+            for line in pending {
+                let _ = writeln!(file, "{}", line);
+            }
+            *log_file = LogFile::Open(file);
+        },
+        Err(err) => {
+            *log_file = LogFile::Disabled;
+            drop(log_file);
+            warn!("Could not open the log file in {}: {}", state_dir.to_string_lossy(), err);
+        }
+    }
+}
+
 fn level_from_verbosity(verbosity: u64) -> log::LevelFilter {
     match verbosity {
         0 => log::LevelFilter::Error,
@@ -0,0 +1,234 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset};
+
+use alias::{Alias, Aliases};
+use feed_util::{Feed, Feeds};
+use history_util::HistoryEntry;
+use store::{self, Store};
+
+/// Keeps everything in memory and never saves anything. Used for dry runs and tests, and by
+/// `CsvStore` to collect the changes of a transaction into a single write.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    pub feeds: Feeds,
+    pub aliases: Aliases,
+    pub history: Vec<HistoryEntry>,
+    pub last_run: Option<DateTime<FixedOffset>>,
+    // number of history entries that came from `load`, the rest were recorded afterwards
+    loaded_history: usize,
+}
+
+impl MemoryStore {
+    /// Copy the current contents of another store.
+    pub fn load(store: &mut Store) -> Result<MemoryStore, Box<Error>> {
+        let history = store.list_history()?;
+
+        Ok(MemoryStore {
+            feeds: store.list_feeds()?,
+            aliases: store.list_aliases()?,
+            last_run: store.last_run()?,
+            loaded_history: history.len(),
+            history: history,
+        })
+    }
+
+    /// History entries recorded since the store was created or loaded.
+    pub fn new_history(&self) -> &[HistoryEntry] {
+        &self.history[self.loaded_history..]
+    }
+}
+
+impl Store for MemoryStore {
+    fn list_feeds(&mut self) -> Result<Feeds, Box<Error>> {
+        Ok(self.feeds.clone())
+    }
+
+    fn insert_feed(&mut self, feed: Feed) -> Result<bool, Box<Error>> {
+        Ok(self.feeds.insert(feed))
+    }
+
+    fn remove_feed(&mut self, feed: &Feed) -> Result<bool, Box<Error>> {
+        Ok(self.feeds.remove(feed))
+    }
+
+    fn update_feed(&mut self, old: &Feed, new: Feed) -> Result<bool, Box<Error>> {
+        if !self.feeds.contains(old) {
+            return Ok(false);
+        }
+        if let Some(existing) = self.feeds.get(&new) {
+            if existing.id != old.id {
+                return Err(store::collision_error(existing));
+            }
+        }
+
+        self.feeds.remove(old);
+        self.feeds.insert(new);
+        Ok(true)
+    }
+
+    fn list_aliases(&mut self) -> Result<Aliases, Box<Error>> {
+        Ok(self.aliases.clone())
+    }
+
+    fn get_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+        Ok(self.aliases.get(name).cloned())
+    }
+
+    fn insert_alias(&mut self, alias: Alias) -> Result<Option<Alias>, Box<Error>> {
+        Ok(self.aliases.insert(alias.name.clone(), alias))
+    }
+
+    fn remove_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+        Ok(self.aliases.remove(name))
+    }
+
+    fn list_history(&mut self) -> Result<Vec<HistoryEntry>, Box<Error>> {
+        Ok(self.history.clone())
+    }
+
+    fn record_history(&mut self, entries: &[HistoryEntry]) -> Result<(), Box<Error>> {
+        self.history.extend_from_slice(entries);
+        Ok(())
+    }
+
+    fn last_run(&mut self) -> Result<Option<DateTime<FixedOffset>>, Box<Error>> {
+        Ok(self.last_run)
+    }
+
+    fn record_run(&mut self, finished: DateTime<FixedOffset>) -> Result<(), Box<Error>> {
+        self.last_run = Some(finished);
+        Ok(())
+    }
+
+    /// Nothing is saved, so there is nothing to back up.
+    fn backup(&mut self, _tag: &str) -> Result<Vec<PathBuf>, Box<Error>> {
+        Ok(Vec::new())
+    }
+
+    fn transaction(&mut self, f: &mut FnMut(&mut Store) -> Result<(), Box<Error>>)
+        -> Result<(), Box<Error>> {
+        let snapshot = self.clone();
+
+        let result = f(self);
+        if result.is_err() {
+            *self = snapshot;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{self, ErrorKind};
+
+    use reqwest::Url;
+
+    fn feed(id: &str, url: &str, filters: &[&str]) -> Feed {
+        let filters = filters.iter().map(|f| f.to_string()).collect();
+        let mut feed = Feed::new(Url::parse(url).unwrap(), filters, &Feeds::new());
+        feed.id = id.to_string();
+        feed
+    }
+
+    fn ids(store: &mut MemoryStore) -> Vec<String> {
+        let mut ids: Vec<String> = store.list_feeds().unwrap().into_iter().map(|f| f.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn inserts_feeds_once() {
+        let mut store = MemoryStore::default();
+
+        assert!(store.insert_feed(feed("a", "https://t.example/rss", &["show"])).unwrap());
+        assert!(store.insert_feed(feed("b", "https://t.example/rss", &["other show"])).unwrap());
+        // the same filters after normalizing
+        assert!(!store.insert_feed(feed("c", "https://t.example/rss", &["SHOW"])).unwrap());
+
+        assert_eq!(ids(&mut store), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn removes_feeds() {
+        let mut store = MemoryStore::default();
+        let a = feed("a", "https://t.example/rss", &[]);
+        store.insert_feed(a.clone()).unwrap();
+
+        assert!(store.remove_feed(&a).unwrap());
+        assert!(!store.remove_feed(&a).unwrap());
+        assert!(ids(&mut store).is_empty());
+    }
+
+    #[test]
+    fn updates_feeds() {
+        let mut store = MemoryStore::default();
+        let a = feed("a", "https://t.example/rss", &["show"]);
+        store.insert_feed(a.clone()).unwrap();
+
+        let mut paused = a.clone();
+        paused.paused = true;
+        assert!(store.update_feed(&a, paused).unwrap());
+        assert!(store.get_feed("a").unwrap().unwrap().paused);
+
+        let mut moved = a.clone();
+        moved.url = Url::parse("https://other.example/rss").unwrap();
+        assert!(store.update_feed(&a, moved.clone()).unwrap());
+        assert_eq!(store.get_feed("a").unwrap().unwrap().url, moved.url);
+
+        // `a` is gone now
+        assert!(!store.update_feed(&a, a.clone()).unwrap());
+    }
+
+    #[test]
+    fn update_does_not_replace_other_feeds() {
+        let mut store = MemoryStore::default();
+        let a = feed("a", "https://t.example/rss", &["show"]);
+        let b = feed("b", "https://t.example/rss", &["other show"]);
+        store.insert_feed(a.clone()).unwrap();
+        store.insert_feed(b.clone()).unwrap();
+
+        let mut collision = a.clone();
+        collision.filters = b.filters.clone();
+        assert!(store.update_feed(&a, collision).is_err());
+
+        assert_eq!(ids(&mut store), vec!["a", "b"]);
+        assert_eq!(store.get_feed("a").unwrap().unwrap().filters, a.filters);
+    }
+
+    #[test]
+    fn transaction_rolls_back_on_error() {
+        let mut store = MemoryStore::default();
+        let a = feed("a", "https://t.example/rss", &[]);
+        store.insert_feed(a.clone()).unwrap();
+
+        let result = store.transaction(&mut |tx| {
+            tx.remove_feed(&a)?;
+            tx.insert_feed(feed("b", "https://other.example/rss", &[]))?;
+            Err(Box::new(io::Error::new(ErrorKind::Other, "failed")))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(ids(&mut store), vec!["a"]);
+    }
+
+    #[test]
+    fn transaction_keeps_changes_on_success() {
+        let mut store = MemoryStore::default();
+        let a = feed("a", "https://t.example/rss", &[]);
+        store.insert_feed(a.clone()).unwrap();
+
+        let result = store.transaction(&mut |tx| {
+            tx.remove_feed(&a)?;
+            tx.insert_feed(feed("b", "https://other.example/rss", &[]))?;
+            Ok(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(ids(&mut store), vec!["b"]);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+
+use reqwest::Url;
+
+use serde_json;
+
+use alias::{Alias, Aliases};
+use feed_util::Feeds;
+use import::{Imported, ImportedFeed};
+
+// Our own attributes are in this namespace so other readers ignore them. Filters are a JSON list,
+// like in feeds.csv, so they round trip exactly.
+const OPML_NAMESPACE: &str = "https://github.com/boustrophedon/rss-torrent";
+const FILTERS_ATTR: &str = "rsstorrent:filters";
+const ALIAS_ATTR: &str = "rsstorrent:alias";
+// aliases are outlines with this instead of xmlUrl, so feed readers don't try to fetch templates
+const ALIAS_URL_ATTR: &str = "rsstorrent:aliasUrl";
+
+// quick-xml's errors don't implement std::error::Error
+fn xml_error<E: fmt::Display>(err: E) -> Box<Error> {
+    Box::new(io::Error::new(ErrorKind::InvalidData, format!("Invalid OPML: {}", err)))
+}
+
+fn outline_attributes(e: &BytesStart) -> Result<HashMap<String, String>, Box<Error>> {
+    let mut attrs = HashMap::new();
+    for attr in e.attributes() {
+        let attr = attr.map_err(xml_error)?;
+        let value = attr.unescaped_value().map_err(xml_error)?;
+        attrs.insert(String::from_utf8_lossy(attr.key).into_owned(),
+                     String::from_utf8_lossy(&value).into_owned());
+    }
+
+    Ok(attrs)
+}
+
+/// Outlines with an `xmlUrl` are feeds and ones with our alias url attribute are aliases. Any
+/// others are just folders, and their children are read as if they weren't nested.
+pub fn parse_opml(contents: &str) -> Result<Imported, Box<Error>> {
+    let mut imported = Imported::default();
+
+    let mut reader = Reader::from_str(contents);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf).map_err(xml_error)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.name() == b"outline" => {
+                let attrs = outline_attributes(e)?;
+                read_outline(&attrs, &mut imported);
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(imported)
+}
+
+fn read_outline(attrs: &HashMap<String, String>, imported: &mut Imported) {
+    let title = attrs.get("text").or_else(|| attrs.get("title")).cloned().unwrap_or_default();
+
+    if let Some(url) = attrs.get(ALIAS_URL_ATTR) {
+        match Url::parse(url) {
+            Ok(url) => imported.aliases.push(Alias { name: title, url: url }),
+            Err(err) => imported.unsupported.push(format!("alias {}: invalid url: {}", title, err)),
+        }
+        return;
+    }
+
+    let url = match attrs.get("xmlUrl") {
+        Some(url) => url,
+        None => return,
+    };
+
+    let url = match Url::parse(url) {
+        Ok(u) => u,
+        Err(err) => {
+            imported.unsupported.push(format!("feed {}: invalid url: {}", title, err));
+            return;
+        }
+    };
+
+    let filters = match attrs.get(FILTERS_ATTR) {
+        Some(filters) => match serde_json::from_str(filters) {
+            Ok(f) => f,
+            Err(err) => {
+                imported.unsupported.push(format!("feed {}: invalid filters: {}", title, err));
+                return;
+            }
+        },
+        None => Vec::new(),
+    };
+
+    imported.feeds.push(ImportedFeed {
+        url: url,
+        filters: filters,
+        last_update: None,
+        alias: attrs.get(ALIAS_ATTR).cloned(),
+    });
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// OPML 2.0 with one outline per feed, and the aliases in a folder of their own. The urls are not
+/// redacted, since the point is to move them somewhere else.
+pub fn write_opml(feeds: &Feeds, aliases: &Aliases) -> Result<String, Box<Error>> {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!("<opml version=\"2.0\" xmlns:rsstorrent=\"{}\">\n", OPML_NAMESPACE));
+    out.push_str("  <head>\n    <title>rss-torrent feeds</title>\n  </head>\n  <body>\n");
+
+    let mut feeds: Vec<_> = feeds.iter().collect();
+    feeds.sort_by(|a, b| a.id.cmp(&b.id));
+
+    for feed in feeds {
+        let title = match feed.alias {
+            Some(ref alias) => alias.clone(),
+            None => feed.url.host_str().unwrap_or("feed").to_string(),
+        };
+        let title = if feed.filters.is_empty() { title }
+                    else { format!("{} ({})", title, feed.filters.join(", ")) };
+
+        out.push_str(&format!("    <outline type=\"rss\" text=\"{}\" xmlUrl=\"{}\" {}=\"{}\"",
+                              xml_escape(&title), xml_escape(feed.url.as_str()), FILTERS_ATTR,
+                              xml_escape(&serde_json::to_string(&feed.filters)?)));
+        if let Some(ref alias) = feed.alias {
+            out.push_str(&format!(" {}=\"{}\"", ALIAS_ATTR, xml_escape(alias)));
+        }
+        out.push_str("/>\n");
+    }
+
+    if !aliases.is_empty() {
+        let mut names: Vec<&String> = aliases.keys().collect();
+        names.sort();
+
+        out.push_str("    <outline text=\"rss-torrent aliases\">\n");
+        for name in names {
+            out.push_str(&format!("      <outline text=\"{}\" {}=\"{}\"/>\n", xml_escape(name),
+                                  ALIAS_URL_ATTR, xml_escape(aliases[name].url.as_str())));
+        }
+        out.push_str("    </outline>\n");
+    }
+
+    out.push_str("  </body>\n</opml>\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use feed_util::Feed;
+
+    fn feed(url: &str, filters: &[&str], alias: Option<&str>, feeds: &Feeds) -> Feed {
+        let filters = filters.iter().map(|f| f.to_string()).collect();
+        let mut feed = Feed::new(Url::parse(url).unwrap(), filters, feeds);
+        feed.alias = alias.map(|a| a.to_string());
+        feed
+    }
+
+    #[test]
+    fn feeds_and_aliases_round_trip() {
+        let mut feeds = Feeds::new();
+        let awkward = feed("https://t.example/rss?a=1&b=2",
+                           &["some show, 1080p", "\"quoted\" 'show'", "<tag> & more"], None, &feeds);
+        feeds.insert(awkward);
+        let aliased = feed("https://t.example/rss?q=some%20show", &["720p"],
+                           Some("tracker:q=some show"), &feeds);
+        feeds.insert(aliased);
+        let bare = feed("https://other.example/feed.xml", &[], None, &feeds);
+        feeds.insert(bare);
+
+        let mut aliases = Aliases::new();
+        for &(name, url) in &[("tracker", "https://t.example/rss?q={q}"),
+                              ("other", "https://other.example/feed.xml?x=\"y\"&z=1")] {
+            let alias = Alias { name: name.to_string(), url: Url::parse(url).unwrap() };
+            aliases.insert(name.to_string(), alias);
+        }
+
+        let opml = write_opml(&feeds, &aliases).unwrap();
+        let imported = parse_opml(&opml).unwrap();
+        assert!(imported.unsupported.is_empty(), "{:?}", imported.unsupported);
+
+        assert_eq!(imported.feeds.len(), feeds.len());
+        for imported_feed in &imported.feeds {
+            let original = feeds.iter().find(|f| f.url == imported_feed.url).unwrap();
+            assert_eq!(imported_feed.filters, original.filters);
+            assert_eq!(imported_feed.alias, original.alias);
+            assert_eq!(imported_feed.last_update, None);
+        }
+
+        let mut imported_aliases = imported.aliases.clone();
+        imported_aliases.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(imported_aliases.len(), 2);
+        for alias in &imported_aliases {
+            assert_eq!(alias.url, aliases[&alias.name].url);
+        }
+    }
+
+    #[test]
+    fn reads_nested_folders_and_reports_bad_outlines() {
+        let opml = r#"<?xml version="1.0"?>
+            <opml version="2.0" xmlns:rsstorrent="https://github.com/boustrophedon/rss-torrent">
+              <body>
+                <outline text="TV">
+                  <outline text="Show" xmlUrl="https://t.example/rss" rsstorrent:filters="[&quot;show&quot;]"/>
+                  <outline text="Other">
+                    <outline text="Plain" type="rss" xmlUrl="https://other.example/rss"/>
+                  </outline>
+                </outline>
+                <outline text="Broken" xmlUrl="not a url"/>
+                <outline text="Bad filters" xmlUrl="https://t.example/rss" rsstorrent:filters="show"/>
+              </body>
+            </opml>"#;
+
+        let imported = parse_opml(opml).unwrap();
+        assert_eq!(imported.feeds.len(), 2);
+        assert_eq!(imported.feeds[0].filters, vec!["show"]);
+        assert!(imported.feeds[1].filters.is_empty());
+        assert_eq!(imported.unsupported.len(), 2);
+    }
+}
@@ -0,0 +1,106 @@
+use csv;
+
+use std::error::Error;
+use std::io;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json;
+
+/// How commands that print listings format them. Tables are for people, JSON and CSV for scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown format {}, expected table, json or csv", s)),
+        }
+    }
+}
+
+/// The number of characters of `cell` that are shown, leaving out ANSI escape sequences like the
+/// ones used for highlighting.
+fn display_width(cell: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in cell.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {},
+            _ => width += 1,
+        }
+    }
+
+    width
+}
+
+/// Print rows as columns padded to the widest cell, with a header line.
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| display_width(h)).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(display_width(cell));
+        }
+    }
+
+    let headers: Vec<String> = headers.iter().map(|h| h.to_uppercase()).collect();
+    print_row(&headers, &widths);
+    for row in rows {
+        print_row(row, &widths);
+    }
+}
+
+fn print_row(row: &[String], widths: &[usize]) {
+    let cells: Vec<String> = row.iter().zip(widths)
+        .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - display_width(cell))))
+        .collect();
+
+    println!("{}", cells.join("  ").trim_end());
+}
+
+pub fn print_csv(headers: &[&str], rows: &[Vec<String>]) -> Result<(), Box<Error>> {
+    let mut writer = csv::Writer::from_writer(io::stdout());
+
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> Result<(), Box<Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_width_skips_escapes() {
+        assert_eq!(display_width("plain"), 5);
+        assert_eq!(display_width("\x1b[1;33mhit\x1b[0m and more"), 12);
+        assert_eq!(display_width("größe"), 5);
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!("csv".parse::<OutputFormat>().unwrap(), OutputFormat::Csv);
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::Local;
+
+use commands::{RTPause, RTResume};
+
+use feed_util::Feed;
+use resolve::select_feeds;
+use store::Store;
+
+pub fn pause_feeds(cmd: RTPause, store: &mut Store) {
+    let selected = match select_feeds(&cmd.url_or_alias, cmd.search_all, &cmd.filters, "pausing",
+                                      store) {
+        Some(feeds) => feeds,
+        None => return,
+    };
+
+    let changes: Vec<(Feed, Feed)> = selected.into_iter()
+        .filter(|feed| {
+            if feed.paused {
+                info!("Feed {} is already paused.", feed);
+            }
+            !feed.paused
+        })
+        .map(|feed| {
+            let mut paused = feed.clone();
+            paused.paused = true;
+            (feed, paused)
+        })
+        .collect();
+
+    apply(changes, "pause", cmd.dry_run, store);
+}
+
+/// Resuming keeps `last_update` from before the pause, so the next update adds everything that
+/// was published in the meantime, unless `--skip-missed` moves it to now.
+pub fn resume_feeds(cmd: RTResume, store: &mut Store) {
+    if !cmd.catch_up && !cmd.skip_missed {
+        error!("Pass --catch-up to add the items published while the feeds were paused, or \
+                --skip-missed to only add items published from now on. Not resuming anything.");
+        return;
+    }
+
+    let selected = match select_feeds(&cmd.url_or_alias, cmd.search_all, &cmd.filters, "resuming",
+                                      store) {
+        Some(feeds) => feeds,
+        None => return,
+    };
+
+    let now = Local::now();
+    let changes: Vec<(Feed, Feed)> = selected.into_iter()
+        .filter(|feed| {
+            if !feed.paused {
+                info!("Feed {} is not paused.", feed);
+            }
+            feed.paused
+        })
+        .map(|feed| {
+            let mut resumed = feed.clone();
+            resumed.paused = false;
+            if cmd.skip_missed {
+                resumed.last_update = Some(now.with_timezone(now.offset()));
+            }
+            (feed, resumed)
+        })
+        .collect();
+
+    apply(changes, "resume", cmd.dry_run, store);
+}
+
+fn apply(changes: Vec<(Feed, Feed)>, verb: &str, dry_run: bool, store: &mut Store) {
+    if changes.is_empty() {
+        return;
+    }
+
+    if dry_run {
+        for &(ref feed, _) in &changes {
+            println!("Would {} {}", verb, feed);
+        }
+        return;
+    }
+
+    let result = store.transaction(&mut |tx| {
+        for &(ref old, ref new) in &changes {
+            tx.update_feed(old, new.clone())?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            for &(ref feed, _) in &changes {
+                info!("Sucessfully {}d feed {}", verb, feed);
+            }
+        },
+        Err(err) => error!("Could not {} feeds: {}", verb, err),
+    }
+}
@@ -0,0 +1,242 @@
+use std::env;
+use std::error::Error;
+
+use commands::RTPreview;
+
+use feed_parse;
+use filter::{self, Condition};
+use import;
+use item::Item;
+use output::{self, OutputFormat};
+use redact::RedactedUrl;
+use resolve;
+use store::Store;
+
+const PREVIEW_HEADERS: &[&str] = &["title", "published", "size", "link", "match"];
+
+const TORRENT_MIME_TYPE: &str = "application/x-bittorrent";
+
+const HIGHLIGHT_START: &str = "\x1b[1;32m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+/// An item as it is printed. `matched` is the first filter that matches it, which is empty if
+/// the feed would have no filters.
+#[derive(Serialize)]
+struct PreviewItem {
+    title: String,
+    published: Option<String>,
+    size: Option<u64>,
+    link_type: &'static str,
+    matches: bool,
+    matched: Option<String>,
+}
+
+pub fn preview_feed(cmd: RTPreview, store: &mut Store) {
+    let filters: Vec<String> = cmd.filters.iter().chain(&cmd.trailing_filters).cloned().collect();
+    let result = match cmd.file {
+        Some(ref path) => import::read_file(path).and_then(|body| feed_parse::parse_feed(&body)),
+        None => {
+            let url_or_alias = cmd.url_or_alias.clone().unwrap_or_default();
+            resolve::resolve_url(store, &url_or_alias).and_then(|url| {
+                debug!("Previewing feed {}", RedactedUrl(&url));
+                feed_parse::fetch_feed(&url)
+            })
+        }
+    };
+
+    let items = match result {
+        Ok(items) => items,
+        Err(err) => {
+            error!("Could not read feed: {}", err);
+            return;
+        }
+    };
+
+    if let Err(err) = print_preview(&items, &filters, cmd.format, !cmd.no_highlight) {
+        error!("Could not print preview: {}", err);
+    }
+}
+
+fn print_preview(items: &[Item], filters: &[String], format: OutputFormat, highlight: bool)
+    -> Result<(), Box<Error>> {
+    let previews: Vec<PreviewItem> = items.iter().map(|item| preview_item(item, filters)).collect();
+
+    let highlight = highlight && format == OutputFormat::Table && env::var_os("NO_COLOR").is_none()
+        && env::var("TERM").map(|t| t != "dumb").unwrap_or(false);
+
+    let rows: Vec<Vec<String>> = previews.iter().map(|p| {
+        let title = match p.matched {
+            Some(ref filter) if highlight => highlight_words(&p.title, filter),
+            _ => p.title.clone(),
+        };
+
+        vec![
+            title,
+            p.published.clone().unwrap_or_default(),
+            p.size.map(human_size).unwrap_or_default(),
+            p.link_type.to_string(),
+            if p.matches { "yes" } else { "no" }.to_string(),
+        ]
+    }).collect();
+
+    match format {
+        OutputFormat::Table => {
+            output::print_table(PREVIEW_HEADERS, &rows);
+            let matching = previews.iter().filter(|p| p.matches).count();
+            println!("{} of {} items match.", matching, previews.len());
+            Ok(())
+        },
+        OutputFormat::Csv => output::print_csv(PREVIEW_HEADERS, &rows),
+        OutputFormat::Json => output::print_json(&previews),
+    }
+}
+
+fn preview_item(item: &Item, filters: &[String]) -> PreviewItem {
+    // the same rule as Feed::matches: no filters match everything
+    let matched = if filters.is_empty() {
+        Some(String::new())
+    }
+    else {
+        filters.iter().find(|f| filter::filter_matches(f, item)).cloned()
+    };
+
+    PreviewItem {
+        title: item.title.clone(),
+        published: item.date().map(|d| d.to_rfc3339()),
+        size: item.size,
+        link_type: link_type(item),
+        matches: matched.is_some(),
+        matched: matched,
+    }
+}
+
+fn link_type(item: &Item) -> &'static str {
+    let is_torrent_file = item.enclosures.first()
+        .map_or(false, |e| e.mime_type.as_ref().map_or(false, |m| m == TORRENT_MIME_TYPE));
+
+    match item.download_url() {
+        Some(url) if url.starts_with("magnet:") => "magnet",
+        Some(url) if is_torrent_file || url.split('?').next().unwrap_or(url).ends_with(".torrent") => {
+            "torrent"
+        },
+        Some(_) => "other",
+        None => "none",
+    }
+}
+
+/// The title with every occurrence of the filter's title words highlighted. Attribute conditions
+/// like `size<4GiB` aren't in the title, so they aren't highlighted.
+fn highlight_words(title: &str, filter: &str) -> String {
+    // lowercased like the filters are matched, which can change the length of non-ascii
+    // characters, so keep track of which character of the title each byte came from
+    let mut lower = String::new();
+    let mut origins = Vec::new();
+    for (i, c) in title.char_indices() {
+        let start = lower.len();
+        lower.extend(c.to_lowercase());
+        origins.extend((start..lower.len()).map(|_| i));
+    }
+
+    let mut highlighted = vec![false; title.len()];
+    for condition in filter.split_whitespace().map(Condition::parse) {
+        if let Condition::Word(word) = condition {
+            for (start, _) in lower.match_indices(word.as_str()) {
+                for &i in &origins[start..start + word.len()] {
+                    highlighted[i] = true;
+                }
+            }
+        }
+    }
+
+    let mut result = String::new();
+    let mut in_highlight = false;
+    for (i, c) in title.char_indices() {
+        if highlighted[i] != in_highlight {
+            result.push_str(if highlighted[i] { HIGHLIGHT_START } else { HIGHLIGHT_END });
+            in_highlight = highlighted[i];
+        }
+        result.push(c);
+    }
+    if in_highlight {
+        result.push_str(HIGHLIGHT_END);
+    }
+
+    result
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_items(body: &str) -> Vec<Item> {
+        feed_parse::parse_feed(body).unwrap()
+    }
+
+    fn highlight(word: &str) -> String {
+        format!("{}{}{}", HIGHLIGHT_START, word, HIGHLIGHT_END)
+    }
+
+    #[test]
+    fn finds_link_types() {
+        let items = fixture_items(include_str!("../test/rss2.xml"));
+        let types: Vec<&str> = items.iter().map(link_type).collect();
+        assert_eq!(types, vec!["torrent", "magnet"]);
+
+        // jackett download links don't end in .torrent, but have the mime type
+        let items = fixture_items(include_str!("../test/torznab.xml"));
+        assert!(items.iter().all(|item| link_type(item) == "torrent"));
+
+        let mut item = items[0].clone();
+        item.enclosures.clear();
+        item.links = vec!["https://t.example/details/1".to_string()];
+        assert_eq!(link_type(&item), "other");
+        item.links.clear();
+        assert_eq!(link_type(&item), "none");
+    }
+
+    #[test]
+    fn highlights_filter_words() {
+        let items = fixture_items(include_str!("../test/rss2.xml"));
+        assert_eq!(highlight_words(&items[0].title, "show 720P size<1GiB"),
+                   format!("Some {} S01E01 {} HDTV x264", highlight("Show"), highlight("720p")));
+        assert_eq!(highlight_words(&items[0].title, "hdtv x264"),
+                   format!("Some Show S01E01 720p {} {}", highlight("HDTV"), highlight("x264")));
+        assert_eq!(highlight_words(&items[0].title, "1080p"), items[0].title);
+    }
+
+    #[test]
+    fn highlights_non_ascii_titles() {
+        assert_eq!(highlight_words("ÜBERWACHUNG Größe S01", "überwachung s01"),
+                   format!("{} Größe {}", highlight("ÜBERWACHUNG"), highlight("S01")));
+        // lowercasing İ adds a combining dot, which must not shift the rest
+        assert_eq!(highlight_words("İstanbul 720p", "720p"),
+                   format!("İstanbul {}", highlight("720p")));
+    }
+
+    #[test]
+    fn formats_sizes() {
+        let items = fixture_items(include_str!("../test/rss2.xml"));
+        assert_eq!(items[0].size.map(human_size), Some("700.0 MiB".to_string()));
+
+        let items = fixture_items(include_str!("../test/torznab.xml"));
+        let sizes: Vec<String> = items.iter().filter_map(|i| i.size).map(human_size).collect();
+        assert_eq!(sizes, vec!["2.0 GiB", "6.0 GiB"]);
+
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use reqwest::Url;
+
+use serde_json;
+
+use import::{Imported, ImportedFeed};
+use rule_util;
+
+/// A rule of qBittorrent's `rss/download_rules.json`. Settings that only change where and how
+/// torrents are added, like the save path and category, are ignored.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadRule {
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default)]
+    must_contain: String,
+    #[serde(default)]
+    must_not_contain: String,
+    #[serde(default)]
+    use_regex: bool,
+    #[serde(default)]
+    episode_filter: String,
+    #[serde(default)]
+    smart_filter: bool,
+    #[serde(default)]
+    affected_feeds: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Read qBittorrent's RSS download rules. Each rule becomes a feed for every feed it applies to,
+/// with one filter per `|` separated alternative of its "must contain" pattern. Rules with
+/// exclusions or episode filters are left out, since importing them would download more than
+/// qBittorrent does.
+pub fn parse_qbittorrent(contents: &str) -> Result<Imported, Box<Error>> {
+    let rules: BTreeMap<String, DownloadRule> = serde_json::from_str(contents)?;
+
+    let mut imported = Imported::default();
+    for (name, rule) in rules {
+        let filters = match translate_rule(&rule) {
+            Ok(filters) => filters,
+            Err(reason) => {
+                imported.unsupported.push(format!("rule {}: {}", name, reason));
+                continue;
+            }
+        };
+
+        if rule.smart_filter {
+            imported.unsupported.push(format!("rule {}: the smart episode filter can't be \
+                                               translated, repeated episodes will be downloaded",
+                                              name));
+        }
+
+        for feed_url in &rule.affected_feeds {
+            match Url::parse(feed_url) {
+                Ok(url) => imported.feeds.push(ImportedFeed {
+                    url: url,
+                    filters: filters.clone(),
+                    last_update: None,
+                    alias: None,
+                }),
+                Err(err) => imported.unsupported.push(format!("rule {}: invalid feed url {}: {}",
+                                                              name, feed_url, err)),
+            }
+        }
+    }
+
+    Ok(imported)
+}
+
+/// The filters of a rule, or why it can't be imported.
+fn translate_rule(rule: &DownloadRule) -> Result<Vec<String>, String> {
+    if !rule.enabled {
+        return Err("it is disabled".to_string());
+    }
+    if !rule.must_not_contain.trim().is_empty() {
+        return Err("\"must not contain\" can't be translated".to_string());
+    }
+    if !rule.episode_filter.trim().is_empty() {
+        return Err("the episode filter can't be translated".to_string());
+    }
+    if rule.affected_feeds.is_empty() {
+        return Err("it isn't used by any feed".to_string());
+    }
+
+    let pattern = rule.must_contain.trim();
+    let alternatives = if rule.use_regex {
+        rule_util::regex_filters(pattern)
+            .ok_or_else(|| format!("the regular expression {} can't be translated", pattern))?
+    }
+    else {
+        // spaces separate words that must all be there, | separates alternatives
+        pattern.split('|').map(rule_util::wildcard_words).collect()
+    };
+
+    // an empty pattern matches everything, which is what a feed without filters does
+    if alternatives.iter().any(|words| words.is_empty()) {
+        return Ok(Vec::new());
+    }
+
+    Ok(alternatives.into_iter().map(|words| words.join(" ")).collect())
+}
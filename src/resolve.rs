@@ -0,0 +1,219 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+
+use reqwest::Url;
+
+use alias::AliasRef;
+use feed_util::{Feed, Feeds};
+use store::Store;
+
+/// What a command's `url_or_alias` (or `id_or_url`) argument refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Id(String),
+    Url(Url),
+}
+
+fn not_found(arg: &str, what: &str) -> Box<Error> {
+    Box::new(io::Error::new(ErrorKind::NotFound, format!("{} is not {}", arg, what)))
+}
+
+fn is_not_found(err: &(Error + 'static)) -> bool {
+    err.downcast_ref::<io::Error>().map_or(false, |e| e.kind() == ErrorKind::NotFound)
+}
+
+/// Resolve a url or an alias, optionally with parameters for its placeholders (see `AliasRef`),
+/// to a url. `name:params` is checked first since it also parses as a url with the scheme `name`.
+pub fn resolve_url(store: &mut Store, url_or_alias: &str) -> Result<Url, Box<Error>> {
+    if url_or_alias.contains(':') {
+        let alias_ref = AliasRef::parse(url_or_alias);
+        if let Ok(ref alias_ref) = alias_ref {
+            if let Some(alias) = store.get_alias(&alias_ref.name)? {
+                return alias.fill(&alias_ref.params);
+            }
+        }
+    }
+
+    if let Ok(url) = Url::parse(url_or_alias) {
+        return Ok(url);
+    }
+
+    trace!("{} is not a valid url, checking if it is an alias...", url_or_alias);
+    match store.get_alias(url_or_alias)? {
+        Some(alias) => alias.fill(&[]),
+        None => Err(not_found(url_or_alias, "a url or a valid alias")),
+    }
+}
+
+/// Resolve a feed id, url or alias. Urls and aliases are checked first, so an alias that happens
+/// to look like an id still works the way it always has. Only arguments that aren't a url or an
+/// alias at all are looked up as ids; errors like a missing alias parameter are returned as is.
+pub fn resolve_target(store: &mut Store, arg: &str) -> Result<Target, Box<Error>> {
+    match resolve_url(store, arg) {
+        Ok(url) => return Ok(Target::Url(url)),
+        Err(err) => if !is_not_found(&*err) {
+            return Err(err);
+        },
+    }
+
+    trace!("{} is not a url or alias, checking if it is a feed id...", arg);
+    match store.get_feed(arg)? {
+        Some(feed) => Ok(Target::Id(feed.id)),
+        None => Err(not_found(arg, "a url, an alias or a feed id")),
+    }
+}
+
+/// The feeds selected by a url, alias or id, or by `--all` and `--filters`, for `delete`, `pause`
+/// and `resume`. `verb` is used in the messages, e.g. "deleting". None if there was an error or
+/// nothing matched, which has already been reported.
+pub fn select_feeds(url_or_alias: &Option<String>, search_all: bool, filters: &[String], verb: &str,
+                store: &mut Store) -> Option<Vec<Feed>> {
+    let target = match *url_or_alias {
+        Some(ref arg) => match resolve_target(store, arg) {
+            Ok(t) => Some(t),
+            Err(err) => {
+                error!("Not {} feeds: {}", verb, err);
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    if target.is_none() && !search_all {
+        error!("No feed given and --all was not passed. Not {} anything.", verb);
+        return None;
+    }
+
+    let result = store.list_feeds();
+
+    let feeds: Feeds;
+    match result {
+        Ok(read_feeds) => feeds = read_feeds,
+        Err(err) => {
+            error!("Could not read feed db: {}. Not {} feeds.", err, verb);
+            return None;
+        },
+    }
+
+    if let Some(Target::Id(_)) = target {
+        if !filters.is_empty() {
+            warn!("Ignoring --filters since a feed id was given.");
+        }
+    }
+
+    let mut selected: Vec<Feed> = match target {
+        Some(ref t) => t.select(&feeds, filters),
+        None => feeds.iter().filter(|f| f.has_filters(filters)).cloned().collect(),
+    };
+    selected.sort_by(|a, b| a.id.cmp(&b.id));
+
+    if selected.is_empty() {
+        warn!("No feeds matched. Not {} anything.", verb);
+        return None;
+    }
+
+    Some(selected)
+}
+
+impl Target {
+    pub fn matches(&self, feed: &Feed) -> bool {
+        match *self {
+            Target::Id(ref id) => feed.id == *id,
+            Target::Url(ref url) => feed.url == *url,
+        }
+    }
+
+    /// All feeds the target refers to. A url target is optionally narrowed down to the feeds that
+    /// have all of `filters` (compared the same way as `Feed::normalized_filters`), an id already
+    /// names a single feed so `filters` are ignored.
+    pub fn select(&self, feeds: &Feeds, filters: &[String]) -> Vec<Feed> {
+        let filters: &[String] = match *self {
+            Target::Id(_) => &[],
+            Target::Url(_) => filters,
+        };
+
+        feeds.iter()
+            .filter(|f| self.matches(f) && f.has_filters(filters))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alias::Alias;
+    use memory_store::MemoryStore;
+
+    fn store() -> MemoryStore {
+        let mut store = MemoryStore::default();
+        let url = Url::parse("https://t.example/rss?q={q}").unwrap();
+        store.insert_alias(Alias { name: "tracker".to_string(), url: url }).unwrap();
+        let feed = Feed::new(Url::parse("https://t.example/rss").unwrap(), Vec::new(), &Feeds::new());
+        store.insert_feed(feed).unwrap();
+        store
+    }
+
+    #[test]
+    fn resolves_urls_aliases_and_ids() {
+        let mut store = store();
+        let id = store.list_feeds().unwrap().into_iter().next().unwrap().id;
+
+        assert_eq!(resolve_target(&mut store, "https://t.example/other").unwrap(),
+                   Target::Url(Url::parse("https://t.example/other").unwrap()));
+        assert_eq!(resolve_target(&mut store, "tracker:q=show").unwrap(),
+                   Target::Url(Url::parse("https://t.example/rss?q=show").unwrap()));
+        assert_eq!(resolve_target(&mut store, &id).unwrap(), Target::Id(id.clone()));
+    }
+
+    #[test]
+    fn ids_ignore_filters() {
+        let mut feeds = Feeds::new();
+        let url = Url::parse("https://t.example/rss").unwrap();
+        let show = Feed::new(url.clone(), vec!["some show".to_string()], &feeds);
+        feeds.insert(show.clone());
+        let other = Feed::new(url.clone(), vec!["other show".to_string()], &feeds);
+        feeds.insert(other.clone());
+
+        let filters = vec!["Other  Show".to_string()];
+        assert_eq!(Target::Id(show.id.clone()).select(&feeds, &filters), vec![show.clone()]);
+        assert_eq!(Target::Url(url.clone()).select(&feeds, &filters), vec![other]);
+        assert_eq!(Target::Url(url).select(&feeds, &[]).len(), 2);
+    }
+
+    #[test]
+    fn selects_feeds_by_target_or_filters() {
+        let mut store = store();
+        let url = Url::parse("https://t.example/rss").unwrap();
+        let feeds = store.list_feeds().unwrap();
+        let show = Feed::new(url.clone(), vec!["some show".to_string()], &feeds);
+        store.insert_feed(show.clone()).unwrap();
+        let filters = vec!["some show".to_string()];
+
+        // the warning about ignored filters is true: the id still selects its feed
+        let bare = feeds.into_iter().next().unwrap();
+        let selected = select_feeds(&Some(bare.id.clone()), false, &filters, "testing", &mut store);
+        assert_eq!(selected, Some(vec![bare]));
+
+        let selected = select_feeds(&Some(url.to_string()), false, &filters, "testing", &mut store);
+        assert_eq!(selected, Some(vec![show.clone()]));
+        let selected = select_feeds(&None, true, &filters, "testing", &mut store);
+        assert_eq!(selected, Some(vec![show]));
+
+        assert_eq!(select_feeds(&None, false, &filters, "testing", &mut store), None);
+        let other = vec!["other show".to_string()];
+        assert_eq!(select_feeds(&None, true, &other, "testing", &mut store), None);
+    }
+
+    #[test]
+    fn passes_alias_errors_through() {
+        let mut store = store();
+
+        let err = resolve_target(&mut store, "tracker").unwrap_err().to_string();
+        assert!(err.contains("needs a value for q"), "{}", err);
+
+        let err = resolve_target(&mut store, "nothing").unwrap_err().to_string();
+        assert!(err.contains("is not a url, an alias or a feed id"), "{}", err);
+    }
+}
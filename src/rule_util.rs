@@ -0,0 +1,169 @@
+//! Translating the download rules of other tools into filters.
+//!
+//! A filter only checks that each of its words appears somewhere in the title, so patterns are
+//! translated by dropping the separators and wildcards between their words. That can make a
+//! rule match a little more than it used to (the words may now appear in any order), but never
+//! less. Patterns that need anything else are not translated.
+
+use filter;
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '.' || c == '_' || c == '-'
+}
+
+fn push_word(words: &mut Vec<String>, word: &mut String) {
+    if !word.is_empty() {
+        words.push(word.to_lowercase());
+        word.clear();
+    }
+}
+
+/// The words of a shell-style wildcard pattern like `The.Show*720p`, where `*` and `?` match
+/// anything.
+pub fn wildcard_words(pattern: &str) -> Vec<String> {
+    pattern.split(|c: char| c == '*' || c == '?' || is_separator(c))
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// The words of a regular expression like `^the[ ._]show.*720p`, or None if it uses anything
+/// besides literal words, separators and repetitions of separators. Anchors are ignored since
+/// filters match anywhere in the title.
+fn regex_words(pattern: &str) -> Option<Vec<String>> {
+    let pattern = pattern.trim().trim_start_matches('^').trim_end_matches('$');
+
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // whether the previous token was a separator, which may be repeated
+    let mut after_separator = false;
+
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '+' | '?' if after_separator => continue,
+            '.' | ' ' | '_' | '-' => {},
+            '\\' => match chars.next() {
+                Some('.') | Some('s') | Some(' ') | Some('_') | Some('-') => {},
+                _ => return None,
+            },
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if class.is_empty() || !class.replace("\\s", " ").replace("\\.", ".").chars()
+                    .all(is_separator) {
+                    return None;
+                }
+            },
+            c if c.is_alphanumeric() => {
+                word.push(c);
+                after_separator = false;
+                continue;
+            },
+            _ => return None,
+        }
+
+        push_word(&mut words, &mut word);
+        after_separator = true;
+    }
+    push_word(&mut words, &mut word);
+
+    Some(words)
+}
+
+/// The word lists of the top level alternatives of a regular expression like `foo|bar.*baz`, or
+/// None if any of them can't be translated.
+pub fn regex_filters(pattern: &str) -> Option<Vec<Vec<String>>> {
+    let mut pattern = pattern.trim();
+    if pattern.starts_with("(?i)") {
+        pattern = &pattern[4..];
+    }
+
+    // groups are rejected by regex_words, so any `|` is at the top level
+    pattern.split('|').map(regex_words).collect()
+}
+
+/// A `size>=...` or `size<=...` condition, or None if `size` isn't understood by the filters.
+pub fn size_condition(op: &str, size: &str) -> Option<String> {
+    let size: String = size.split_whitespace().collect();
+    filter::parse_size(&size).map(|_| format!("size{}{}", op, size))
+}
+
+/// Every combination of one word list from each of `choices`, appended to `base`. Used to expand
+/// things like a list of allowed resolutions into one filter per resolution.
+pub fn combine(base: Vec<String>, choices: &[Vec<Vec<String>>]) -> Vec<Vec<String>> {
+    let mut combined = vec![base];
+    for options in choices {
+        if options.is_empty() {
+            continue;
+        }
+
+        combined = combined.iter()
+            .flat_map(|words| options.iter().map(move |option| {
+                let mut words = words.clone();
+                words.extend(option.iter().cloned());
+                words
+            }))
+            .collect();
+    }
+
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn translates_wildcards() {
+        let table: &[(&str, &[&str])] = &[
+            ("The.Show*720p", &["the", "show", "720p"]),
+            ("*some_show?s01*", &["some", "show", "s01"]),
+            ("Some Show - 1080p", &["some", "show", "1080p"]),
+            ("**", &[]),
+            ("", &[]),
+        ];
+
+        for &(pattern, expected) in table {
+            assert_eq!(wildcard_words(pattern), words(expected), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn translates_simple_regexes() {
+        let table: &[(&str, &[&[&str]])] = &[
+            ("^The[ ._]Show.*720p$", &[&["the", "show", "720p"]]),
+            ("the\\.show\\s+1080p", &[&["the", "show", "1080p"]]),
+            ("some_show-s01 web", &[&["some", "show", "s01", "web"]]),
+            ("[\\s.]?show[._-]*s02", &[&["show", "s02"]]),
+            ("(?i)Foo|bar.*baz", &[&["foo"], &["bar", "baz"]]),
+        ];
+
+        for &(pattern, expected) in table {
+            let expected: Vec<Vec<String>> = expected.iter().map(|w| words(w)).collect();
+            assert_eq!(regex_filters(pattern), Some(expected), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn rejects_other_regexes() {
+        let table = &[
+            "(foo|bar)",
+            "show[0-9]",
+            "s\\d+e\\d+",
+            "colou?r",
+            "show+",
+            "show[]",
+            "*show",
+            "foo|ba(r)",
+            "show$|x{2}",
+        ];
+
+        for pattern in table {
+            assert_eq!(regex_filters(pattern), None, "{}", pattern);
+        }
+    }
+}
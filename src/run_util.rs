@@ -0,0 +1,47 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+
+use db_util;
+
+pub const LAST_RUN_FILENAME: &str = "last_run.txt";
+
+fn open_or_create_last_run(state_dir: &Path) -> Result<File, Box<Error>> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(db_util::db_path(state_dir, LAST_RUN_FILENAME, "Last run"))?)
+}
+
+/// Read the time the last successful update finished, which is a single RFC 3339 date. The file is
+/// empty or missing if there hasn't been one yet.
+pub fn read_last_run(state_dir: &Path) -> Result<Option<DateTime<FixedOffset>>, Box<Error>> {
+    debug!("Reading last run.");
+
+    let mut file = match db_util::open_existing_db(state_dir, LAST_RUN_FILENAME, "Last run")? {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    let contents = contents.trim();
+    if contents.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(DateTime::parse_from_rfc3339(contents)?))
+}
+
+pub fn write_last_run(state_dir: &Path, finished: DateTime<FixedOffset>) -> Result<(), Box<Error>> {
+    debug!("Writing last run.");
+
+    let mut file = open_or_create_last_run(state_dir)?;
+    db_util::overwrite_db(&mut file, format!("{}\n", finished.to_rfc3339()).as_bytes())
+}
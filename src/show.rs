@@ -0,0 +1,47 @@
+use commands::RTShow;
+
+use feed_util::Feeds;
+use redact::{self, RedactedUrl};
+use resolve;
+use store::Store;
+
+pub fn show_feed(cmd: RTShow, store: &mut Store) {
+    let target = match resolve::resolve_target(store, &cmd.id_or_url) {
+        Ok(t) => t,
+        Err(err) => {
+            error!("Could not show feed: {}", err);
+            return;
+        }
+    };
+
+    let result = store.list_feeds();
+
+    let feeds: Feeds;
+    match result {
+        Ok(read_feeds) => feeds = read_feeds,
+        Err(err) => {
+            error!("Could not read feed db: {}.", err);
+            return;
+        },
+    }
+
+    let mut selected = target.select(&feeds, &[]);
+    selected.sort_by(|a, b| a.id.cmp(&b.id));
+
+    if selected.is_empty() {
+        warn!("No feeds found for {}", cmd.id_or_url);
+    }
+
+    for feed in selected {
+        println!("id:          {}", feed.id);
+        println!("url:         {}", RedactedUrl(&feed.url));
+        if let Some(ref alias) = feed.alias {
+            println!("alias:       {}", redact::redact_alias_ref(alias));
+        }
+        println!("filters:     {}", feed.filters.join(", "));
+        println!("last update: {}", feed.last_update.map(|d| d.to_rfc3339()).unwrap_or_else(|| "never".to_string()));
+        println!("paused:      {}", if feed.paused { "yes" } else { "no" });
+        println!("failures:    {}", feed.failures);
+        println!();
+    }
+}
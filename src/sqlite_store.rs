@@ -0,0 +1,410 @@
+use std::error::Error;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+
+use rusqlite::{Connection, types::ToSql};
+
+use serde_json;
+
+use reqwest::Url;
+
+use alias::{Alias, Aliases};
+use db_util;
+use feed_util::{Feed, Feeds};
+use history_util::HistoryEntry;
+use store::{self, Store};
+
+const SQLITE_DB_FILENAME: &str = "rss-torrent.sqlite";
+
+// Migration n (1-indexed) brings the schema from version n-1 to version n, where the version is
+// stored in sqlite's user_version pragma. Never change a migration that has been released, add a
+// new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema, mirroring the csv files, plus the time of each update run
+    "CREATE TABLE feeds (
+        id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        last_update TEXT,
+        filters TEXT NOT NULL,
+        paused INTEGER NOT NULL DEFAULT 0,
+        failures INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        last_match TEXT,
+        alias TEXT,
+        added TEXT,
+        last_fetch TEXT
+    );
+    CREATE UNIQUE INDEX feeds_id ON feeds (id);
+    CREATE TABLE aliases (
+        name TEXT PRIMARY KEY NOT NULL,
+        url TEXT NOT NULL
+    );
+    CREATE TABLE history (
+        feed_url TEXT NOT NULL,
+        title TEXT NOT NULL,
+        guid TEXT,
+        info_hash TEXT,
+        dispatched TEXT NOT NULL
+    );
+    CREATE INDEX history_info_hash ON history (info_hash);
+    CREATE TABLE runs (
+        finished TEXT NOT NULL
+    );",
+];
+
+fn parse_date(date: &Option<String>) -> Result<Option<DateTime<FixedOffset>>, Box<Error>> {
+    match *date {
+        Some(ref d) => Ok(Some(DateTime::parse_from_rfc3339(d)?)),
+        None => Ok(None),
+    }
+}
+
+/// Feeds, aliases and history in a single sqlite database in the data directory. Dates are
+/// stored as RFC 3339 strings and filters as a JSON list.
+pub struct SqliteStore {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn open(data_dir: &Path) -> Result<SqliteStore, Box<Error>> {
+        let mut db_path = data_dir.to_path_buf();
+        db_path.push(SQLITE_DB_FILENAME);
+
+        if !db_path.exists() {
+            warn!("Sqlite db not found at {}, creating.", db_path.to_string_lossy());
+        }
+        else {
+            trace!("Sqlite db found at {}.", db_path.to_string_lossy());
+        }
+
+        let mut store = SqliteStore { conn: Connection::open(&db_path)?, path: db_path };
+        store.migrate()?;
+
+        Ok(store)
+    }
+
+    /// Whether the database exists, without creating it like `open` does.
+    pub fn exists(data_dir: &Path) -> bool {
+        data_dir.join(SQLITE_DB_FILENAME).exists()
+    }
+
+    fn schema_version(&self) -> Result<usize, Box<Error>> {
+        let version: i64 = self.conn.query_row("PRAGMA user_version", &[], |row| row.get(0))?;
+        Ok(version as usize)
+    }
+
+    /// Bring the schema up to date, one migration per transaction.
+    fn migrate(&mut self) -> Result<(), Box<Error>> {
+        let version = self.schema_version()?;
+
+        if version > MIGRATIONS.len() {
+            return Err(Box::new(io::Error::new(ErrorKind::InvalidData,
+                format!("Sqlite db has schema version {} but this version of rss-torrent only \
+                         understands up to version {}", version, MIGRATIONS.len()))));
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            info!("Migrating sqlite db to schema version {}.", i + 1);
+
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Run `f` inside a savepoint. Unlike `Connection::transaction` these can be nested, so the
+    /// individual operations can be used inside `Store::transaction`.
+    fn savepoint<T, F>(&mut self, f: F) -> Result<T, Box<Error>>
+        where F: FnOnce(&mut SqliteStore) -> Result<T, Box<Error>> {
+        self.conn.execute_batch("SAVEPOINT rss_torrent")?;
+
+        match f(self) {
+            Ok(value) => {
+                self.conn.execute_batch("RELEASE rss_torrent")?;
+                Ok(value)
+            },
+            Err(err) => {
+                if let Err(rollback_err) = self.conn.execute_batch("ROLLBACK TO rss_torrent; RELEASE rss_torrent") {
+                    error!("Could not roll back sqlite db: {}", rollback_err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn insert_feed_row(&self, feed: &Feed) -> Result<(), Box<Error>> {
+        let last_update = feed.last_update.map(|d| d.to_rfc3339());
+        let last_match = feed.last_match.map(|d| d.to_rfc3339());
+        let added = feed.added.map(|d| d.to_rfc3339());
+        let last_fetch = feed.last_fetch.map(|d| d.to_rfc3339());
+        let filters = serde_json::to_string(&feed.filters)?;
+
+        self.conn.execute("INSERT INTO feeds (id, url, last_update, filters, paused, failures, \
+                                              last_error, last_match, alias, added, last_fetch) \
+                           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                          &[&feed.id as &ToSql, &feed.url.as_str(), &last_update, &filters,
+                            &feed.paused, &(feed.failures as i64), &feed.last_error, &last_match,
+                            &feed.alias, &added, &last_fetch])?;
+        Ok(())
+    }
+
+    fn delete_feed_row(&self, feed: &Feed) -> Result<bool, Box<Error>> {
+        let deleted = self.conn.execute("DELETE FROM feeds WHERE id = ?1", &[&feed.id])?;
+        Ok(deleted > 0)
+    }
+}
+
+impl Store for SqliteStore {
+    fn list_feeds(&mut self) -> Result<Feeds, Box<Error>> {
+        debug!("Reading feeds from sqlite db.");
+
+        let mut feeds = Feeds::new();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, url, last_update, filters, paused, failures, last_error, last_match, alias, \
+                    added, last_fetch \
+             FROM feeds")?;
+        let rows = stmt.query_map(&[], |row| {
+            let id: String = row.get(0);
+            let url: String = row.get(1);
+            let filters: String = row.get(3);
+            let paused: bool = row.get(4);
+            let failures: i64 = row.get(5);
+            let last_error: Option<String> = row.get(6);
+            let alias: Option<String> = row.get(8);
+            // last_update, last_match, added and last_fetch
+            let dates: [Option<String>; 4] = [row.get(2), row.get(7), row.get(9), row.get(10)];
+            (id, url, filters, paused, failures, last_error, alias, dates)
+        })?;
+
+        for row in rows {
+            let (id, url, filters, paused, failures, last_error, alias, dates) = row?;
+
+            feeds.insert(Feed {
+                id: id,
+                url: Url::parse(&url)?,
+                last_update: parse_date(&dates[0])?,
+                filters: serde_json::from_str(&filters)?,
+                paused: paused,
+                failures: failures as u32,
+                last_error: last_error,
+                last_match: parse_date(&dates[1])?,
+                alias: alias,
+                added: parse_date(&dates[2])?,
+                last_fetch: parse_date(&dates[3])?,
+            });
+        }
+
+        Ok(feeds)
+    }
+
+    fn insert_feed(&mut self, feed: Feed) -> Result<bool, Box<Error>> {
+        // equality depends on the normalized filters, which can't be done in sql
+        self.savepoint(|store| {
+            if store.list_feeds()?.contains(&feed) {
+                return Ok(false);
+            }
+
+            store.insert_feed_row(&feed)?;
+            Ok(true)
+        })
+    }
+
+    fn remove_feed(&mut self, feed: &Feed) -> Result<bool, Box<Error>> {
+        self.delete_feed_row(feed)
+    }
+
+    fn update_feed(&mut self, old: &Feed, new: Feed) -> Result<bool, Box<Error>> {
+        self.savepoint(|store| {
+            if !store.delete_feed_row(old)? {
+                return Ok(false);
+            }
+            // returning an error rolls back the delete
+            if let Some(existing) = store.list_feeds()?.get(&new) {
+                return Err(store::collision_error(existing));
+            }
+
+            store.insert_feed_row(&new)?;
+            Ok(true)
+        })
+    }
+
+    fn list_aliases(&mut self) -> Result<Aliases, Box<Error>> {
+        debug!("Reading aliases from sqlite db.");
+
+        let mut aliases = Aliases::new();
+
+        let mut stmt = self.conn.prepare("SELECT name, url FROM aliases")?;
+        let rows = stmt.query_map(&[], |row| {
+            let name: String = row.get(0);
+            let url: String = row.get(1);
+            (name, url)
+        })?;
+
+        for row in rows {
+            let (name, url) = row?;
+            aliases.insert(name.clone(), Alias { name: name, url: Url::parse(&url)? });
+        }
+
+        Ok(aliases)
+    }
+
+    fn get_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+        let mut stmt = self.conn.prepare("SELECT url FROM aliases WHERE name = ?1")?;
+        let mut rows = stmt.query_map(&[&name], |row| {
+            let url: String = row.get(0);
+            url
+        })?;
+
+        match rows.next() {
+            Some(url) => Ok(Some(Alias { name: name.to_string(), url: Url::parse(&url?)? })),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_alias(&mut self, alias: Alias) -> Result<Option<Alias>, Box<Error>> {
+        self.savepoint(|store| {
+            let old = store.get_alias(&alias.name)?;
+            store.conn.execute("INSERT OR REPLACE INTO aliases (name, url) VALUES (?1, ?2)",
+                               &[&alias.name as &ToSql, &alias.url.as_str()])?;
+            Ok(old)
+        })
+    }
+
+    fn remove_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+        self.savepoint(|store| {
+            let old = store.get_alias(name)?;
+            store.conn.execute("DELETE FROM aliases WHERE name = ?1", &[&name])?;
+            Ok(old)
+        })
+    }
+
+    fn list_history(&mut self) -> Result<Vec<HistoryEntry>, Box<Error>> {
+        debug!("Reading history from sqlite db.");
+
+        let mut history = Vec::new();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT feed_url, title, guid, info_hash, dispatched FROM history ORDER BY rowid")?;
+        let rows = stmt.query_map(&[], |row| {
+            let feed_url: String = row.get(0);
+            let title: String = row.get(1);
+            let guid: Option<String> = row.get(2);
+            let info_hash: Option<String> = row.get(3);
+            let dispatched: String = row.get(4);
+            (feed_url, title, guid, info_hash, dispatched)
+        })?;
+
+        for row in rows {
+            let (feed_url, title, guid, info_hash, dispatched) = row?;
+            history.push(HistoryEntry {
+                feed_url: feed_url,
+                title: title,
+                guid: guid,
+                info_hash: info_hash,
+                dispatched: DateTime::parse_from_rfc3339(&dispatched)?,
+            });
+        }
+
+        Ok(history)
+    }
+
+    fn record_history(&mut self, entries: &[HistoryEntry]) -> Result<(), Box<Error>> {
+        debug!("Appending {} entries to sqlite history.", entries.len());
+
+        self.savepoint(|store| {
+            for entry in entries {
+                store.conn.execute("INSERT INTO history (feed_url, title, guid, info_hash, dispatched) \
+                                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                                   &[&entry.feed_url as &ToSql, &entry.title, &entry.guid,
+                                     &entry.info_hash, &entry.dispatched.to_rfc3339()])?;
+            }
+            Ok(())
+        })
+    }
+
+    fn last_run(&mut self) -> Result<Option<DateTime<FixedOffset>>, Box<Error>> {
+        let mut stmt = self.conn.prepare("SELECT finished FROM runs ORDER BY rowid DESC LIMIT 1")?;
+        let mut rows = stmt.query_map(&[], |row| {
+            let finished: String = row.get(0);
+            finished
+        })?;
+
+        match rows.next() {
+            Some(finished) => Ok(Some(DateTime::parse_from_rfc3339(&finished?)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn record_run(&mut self, finished: DateTime<FixedOffset>) -> Result<(), Box<Error>> {
+        // only the latest run is needed
+        self.savepoint(|store| {
+            store.conn.execute("DELETE FROM runs", &[])?;
+            store.conn.execute("INSERT INTO runs (finished) VALUES (?1)", &[&finished.to_rfc3339()])?;
+            Ok(())
+        })
+    }
+
+    /// Copies the whole database file, so the history is backed up too.
+    fn backup(&mut self, tag: &str) -> Result<Vec<PathBuf>, Box<Error>> {
+        Ok(db_util::backup_file(&self.path, tag)?.into_iter().collect())
+    }
+
+    fn transaction(&mut self, f: &mut FnMut(&mut Store) -> Result<(), Box<Error>>)
+        -> Result<(), Box<Error>> {
+        self.savepoint(|store| f(store))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    #[test]
+    fn creates_the_current_schema() {
+        let dir = TempDir::new("sqlite_store").unwrap();
+        let mut store = SqliteStore::open(dir.path()).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+
+        let url = Url::parse("https://t.example/rss").unwrap();
+        let mut feed = Feed::new(url, vec!["show, \"1080p\"".to_string()], &Feeds::new());
+        feed.paused = true;
+        feed.failures = 2;
+        feed.last_error = Some("timed out".to_string());
+        feed.last_match = feed.added;
+        feed.alias = Some("tracker".to_string());
+        feed.last_fetch = feed.added;
+        store.insert_feed(feed.clone()).unwrap();
+
+        // reopening doesn't migrate again
+        let mut store = SqliteStore::open(dir.path()).unwrap();
+        let read = store.get_feed(&feed.id).unwrap().unwrap();
+        assert_eq!(read.filters, feed.filters);
+        assert!(read.paused);
+        assert_eq!(read.failures, 2);
+        assert_eq!(read.last_error, feed.last_error);
+        assert_eq!(read.last_match, feed.last_match);
+        assert_eq!(read.alias, feed.alias);
+        assert_eq!(read.added, feed.added);
+        assert_eq!(read.last_fetch, feed.last_fetch);
+    }
+
+    #[test]
+    fn refuses_newer_schemas() {
+        let dir = TempDir::new("sqlite_store").unwrap();
+        let store = SqliteStore::open(dir.path()).unwrap();
+        store.conn.execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len() + 1))
+            .unwrap();
+        drop(store);
+
+        assert!(SqliteStore::open(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Duration, FixedOffset, Local};
+
+use commands::RTStatus;
+use feed_util::{Feed, Feeds};
+use redact;
+use store::Store;
+
+/// How many feeds to show in the most and least recently updated lists.
+const RECENT_COUNT: usize = 5;
+
+/// Print a summary of the health of all feeds. Returns false if there are problems: feeds that are
+/// failing or haven't matched anything in `cmd.days` days, or no successful update run at all.
+/// Paused feeds are never counted as problems.
+pub fn show_status(cmd: RTStatus, store: &mut Store) -> bool {
+    let result = store.list_feeds();
+
+    let feeds: Feeds;
+    match result {
+        Ok(read_feeds) => feeds = read_feeds,
+        Err(err) => {
+            error!("Could not read feed db: {}.", err);
+            return false;
+        },
+    }
+
+    let last_run = match store.last_run() {
+        Ok(last_run) => last_run,
+        Err(err) => {
+            error!("Could not read last update run: {}.", err);
+            return false;
+        }
+    };
+
+    let now = Local::now();
+    let now = now.with_timezone(now.offset());
+    let mut healthy = true;
+
+    match last_run {
+        Some(finished) => println!("Last successful update: {} ({} ago)", finished.to_rfc3339(),
+                                   format_age(now.signed_duration_since(finished))),
+        None => {
+            println!("Last successful update: never");
+            healthy = false;
+        }
+    }
+
+    let paused = feeds.iter().filter(|f| f.paused).count();
+    println!("Feeds: {} ({} paused)", feeds.len(), paused);
+
+    // None sorts first, so feeds that were never updated are the least recent
+    let mut by_update: Vec<&Feed> = feeds.iter().collect();
+    by_update.sort_by(|a, b| a.last_update.cmp(&b.last_update).then_with(|| a.id.cmp(&b.id)));
+
+    println!();
+    println!("Most recently updated:");
+    for feed in by_update.iter().rev().filter(|f| f.last_update.is_some()).take(RECENT_COUNT) {
+        println!("  {}: {}", feed, format_date(feed.last_update, now));
+    }
+
+    println!();
+    println!("Least recently updated:");
+    for feed in by_update.iter().take(RECENT_COUNT) {
+        println!("  {}: {}", feed, format_date(feed.last_update, now));
+    }
+
+    let mut failing: Vec<&Feed> = feeds.iter().filter(|f| !f.paused && f.failures > 0).collect();
+    failing.sort_by(|a, b| b.failures.cmp(&a.failures).then_with(|| a.id.cmp(&b.id)));
+
+    if !failing.is_empty() {
+        healthy = false;
+
+        println!();
+        println!("Failing:");
+        for feed in &failing {
+            let error = feed.last_error.as_ref().map(|e| redact::redact_str(e))
+                .unwrap_or_else(|| "unknown error".to_string());
+            println!("  {}: failed {} times in a row, last error: {}", feed, feed.failures, error);
+        }
+    }
+
+    // feeds that were never fetched successfully are already reported as failing, and recently
+    // added feeds haven't had the time to match anything
+    let cutoff = now - Duration::days(cmd.days as i64);
+    let mut stale: Vec<&Feed> = feeds.iter()
+        .filter(|f| !f.paused && f.last_fetch.is_some()
+                && f.added.max(f.last_match).map_or(true, |d| d < cutoff))
+        .collect();
+    stale.sort_by(|a, b| a.last_match.cmp(&b.last_match).then_with(|| a.id.cmp(&b.id)));
+
+    if !stale.is_empty() {
+        healthy = false;
+
+        println!();
+        println!("No matches in {} days:", cmd.days);
+        for feed in &stale {
+            println!("  {}: last match {}", feed, format_date(feed.last_match, now));
+        }
+    }
+
+    // items that could not be added are retried too, since the feed's last update stays before
+    // them, but nothing records how many there are
+    println!();
+    println!("Failing feeds to retry on the next update: {}", failing.len());
+
+    healthy
+}
+
+fn format_date(date: Option<DateTime<FixedOffset>>, now: DateTime<FixedOffset>) -> String {
+    match date {
+        Some(d) => format!("{} ({} ago)", d.to_rfc3339(), format_age(now.signed_duration_since(d))),
+        None => "never".to_string(),
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    if age.num_days() > 0 {
+        format!("{}d {}h", age.num_days(), age.num_hours() % 24)
+    }
+    else if age.num_hours() > 0 {
+        format!("{}h {}m", age.num_hours(), age.num_minutes() % 60)
+    }
+    else {
+        format!("{}m", age.num_minutes().max(0))
+    }
+}
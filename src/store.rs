@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset};
+
+use alias::{Alias, Aliases};
+use config::RTConfig;
+use csv_store::CsvStore;
+use feed_util::{Feed, Feeds};
+use history_util::HistoryEntry;
+use sqlite_store::SqliteStore;
+
+/// Which storage backend the databases are kept in. CSV is the default so the files can be
+/// inspected and edited by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Csv,
+    Sqlite,
+}
+
+impl Default for Backend {
+    fn default() -> Backend {
+        Backend::Csv
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s {
+            "csv" => Ok(Backend::Csv),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(format!("Unknown storage backend {}, expected csv or sqlite", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Backend::Csv => write!(f, "csv"),
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// Persistence for feeds, aliases and history. Each operation is saved immediately unless it is
+/// run inside `transaction`. History is only ever appended to.
+pub trait Store {
+    fn list_feeds(&mut self) -> Result<Feeds, Box<Error>>;
+
+    fn get_feed(&mut self, id: &str) -> Result<Option<Feed>, Box<Error>> {
+        Ok(self.list_feeds()?.into_iter().find(|f| f.id == id))
+    }
+
+    /// Returns false without inserting anything if an equal feed already exists. See `Feed` for
+    /// what equal means.
+    fn insert_feed(&mut self, feed: Feed) -> Result<bool, Box<Error>>;
+
+    /// Returns false if the feed did not exist.
+    fn remove_feed(&mut self, feed: &Feed) -> Result<bool, Box<Error>>;
+
+    /// Replace `old` with `new`. Returns false without changing anything if `old` did not exist,
+    /// and an error without changing anything if `new` is equal to one of the other feeds.
+    fn update_feed(&mut self, old: &Feed, new: Feed) -> Result<bool, Box<Error>>;
+
+    fn list_aliases(&mut self) -> Result<Aliases, Box<Error>>;
+
+    fn get_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>> {
+        Ok(self.list_aliases()?.remove(name))
+    }
+
+    /// Insert or replace an alias, returning the old one if there was one.
+    fn insert_alias(&mut self, alias: Alias) -> Result<Option<Alias>, Box<Error>>;
+
+    /// Returns the removed alias, or `None` if it did not exist.
+    fn remove_alias(&mut self, name: &str) -> Result<Option<Alias>, Box<Error>>;
+
+    fn list_history(&mut self) -> Result<Vec<HistoryEntry>, Box<Error>>;
+
+    fn record_history(&mut self, entries: &[HistoryEntry]) -> Result<(), Box<Error>>;
+
+    /// When the last successful update run finished, if there ever was one.
+    fn last_run(&mut self) -> Result<Option<DateTime<FixedOffset>>, Box<Error>>;
+
+    fn record_run(&mut self, finished: DateTime<FixedOffset>) -> Result<(), Box<Error>>;
+
+    /// Copy the feed and alias databases aside before a big change like an import, returning
+    /// where the copies are. `tag` goes in the backup file names.
+    fn backup(&mut self, tag: &str) -> Result<Vec<PathBuf>, Box<Error>>;
+
+    /// Run `f` so that either all of its changes are saved or, if it returns an error, none are.
+    fn transaction(&mut self, f: &mut FnMut(&mut Store) -> Result<(), Box<Error>>)
+        -> Result<(), Box<Error>>;
+}
+
+/// The error `Store::update_feed` returns when the changed feed is equal to `existing`.
+pub fn collision_error(existing: &Feed) -> Box<Error> {
+    Box::new(io::Error::new(ErrorKind::AlreadyExists,
+                            format!("Feed {} already has the same url and filters", existing)))
+}
+
+pub fn open_backend(backend: Backend, config: &RTConfig) -> Result<Box<Store>, Box<Error>> {
+    match backend {
+        Backend::Csv => Ok(Box::new(CsvStore::new(&config.data_dir, &config.state_dir))),
+        Backend::Sqlite => Ok(Box::new(SqliteStore::open(config.data_dir.as_path())?)),
+    }
+}
+
+/// Whether `backend` has any data yet. Opening a backend creates it, so check this first when
+/// nothing should be created.
+pub fn backend_exists(backend: Backend, config: &RTConfig) -> bool {
+    match backend {
+        Backend::Csv => CsvStore::exists(&config.data_dir, &config.state_dir),
+        Backend::Sqlite => SqliteStore::exists(&config.data_dir),
+    }
+}
+
+/// Open the backend selected in the config.
+pub fn open_store(config: &RTConfig) -> Result<Box<Store>, Box<Error>> {
+    debug!("Opening {} store.", config.storage);
+    open_backend(config.storage, config)
+}
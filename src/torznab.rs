@@ -0,0 +1,123 @@
+use rss::extension::ExtensionMap;
+
+use item::Item;
+
+// Torznab/Newznab feeds (Jackett, Prowlarr, etc.) put metadata in
+// <torznab:attr name="seeders" value="12"/> elements. Newznab uses the same layout.
+const NAMESPACES: &[&str] = &["torznab", "newznab"];
+
+/// Fill in the item fields that the torznab attributes of `extensions` provide.
+pub fn apply_attrs(item: &mut Item, extensions: &ExtensionMap) {
+    for ns in NAMESPACES {
+        let attrs = match extensions.get(*ns).and_then(|e| e.get("attr")) {
+            Some(attrs) => attrs,
+            None => continue,
+        };
+
+        for attr in attrs {
+            let name = attr.attrs().get("name");
+            let value = attr.attrs().get("value");
+            let (name, value) = match (name, value) {
+                (Some(n), Some(v)) => (n.as_str(), v.trim()),
+                _ => {
+                    trace!("Ignoring {}:attr without name or value", ns);
+                    continue;
+                }
+            };
+
+            match name {
+                "seeders" => item.seeders = value.parse().ok(),
+                "size" => item.size = value.parse().ok(),
+                "infohash" => item.info_hash = Some(value.to_lowercase()),
+                "imdbid" | "imdb" => item.imdb_id = Some(normalize_imdb_id(value)),
+                "category" => {
+                    if !item.categories.iter().any(|c| c == value) {
+                        item.categories.push(value.to_string());
+                    }
+                },
+                _ => trace!("Ignoring {}:attr {}", ns, name),
+            }
+        }
+    }
+}
+
+/// Torznab gives imdb ids without the "tt" prefix, but that's how everyone else writes them.
+fn normalize_imdb_id(id: &str) -> String {
+    if id.starts_with("tt") { id.to_string() }
+    else { format!("tt{:0>7}", id) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rss::Channel;
+
+    fn empty_item() -> Item {
+        Item {
+            title: String::new(),
+            links: Vec::new(),
+            enclosures: Vec::new(),
+            id: None,
+            published: None,
+            updated: None,
+            categories: Vec::new(),
+            size: None,
+            seeders: None,
+            info_hash: None,
+            magnet: None,
+            imdb_id: None,
+        }
+    }
+
+    fn fixture_extensions() -> Vec<ExtensionMap> {
+        let channel = Channel::read_from(include_str!("../test/torznab.xml").as_bytes()).unwrap();
+        channel.items().iter().map(|item| item.extensions().clone()).collect()
+    }
+
+    #[test]
+    fn applies_fixture_attrs() {
+        let extensions = fixture_extensions();
+        assert_eq!(extensions.len(), 2);
+
+        let mut item = empty_item();
+        apply_attrs(&mut item, &extensions[0]);
+        assert_eq!(item.seeders, Some(42));
+        assert_eq!(item.size, Some(2147483648));
+        assert_eq!(item.info_hash.as_ref().unwrap(), "0123456789abcdef0123456789abcdef01234567");
+        assert_eq!(item.imdb_id.as_ref().unwrap(), "tt0944947");
+        assert_eq!(item.categories, vec!["5040"]);
+
+        let mut item = empty_item();
+        apply_attrs(&mut item, &extensions[1]);
+        assert_eq!(item.seeders, Some(3));
+        assert_eq!(item.size, Some(6442450944));
+        assert_eq!(item.categories, vec!["5045"]);
+    }
+
+    #[test]
+    fn does_not_repeat_categories() {
+        let extensions = fixture_extensions();
+
+        let mut item = empty_item();
+        item.categories.push("5040".to_string());
+        apply_attrs(&mut item, &extensions[0]);
+        assert_eq!(item.categories, vec!["5040"]);
+    }
+
+    #[test]
+    fn ignores_items_without_attrs() {
+        let mut item = empty_item();
+        apply_attrs(&mut item, &ExtensionMap::default());
+        assert_eq!(item.seeders, None);
+        assert_eq!(item.size, None);
+        assert!(item.categories.is_empty());
+    }
+
+    #[test]
+    fn normalizes_imdb_ids() {
+        assert_eq!(normalize_imdb_id("0944947"), "tt0944947");
+        assert_eq!(normalize_imdb_id("944947"), "tt0944947");
+        assert_eq!(normalize_imdb_id("tt0944947"), "tt0944947");
+    }
+}
@@ -0,0 +1,238 @@
+use rss::extension::ExtensionMap;
+
+use filter;
+use item::Item;
+
+// Trackers that put torrent metadata in their own rss namespaces. The rss crate keys extensions
+// by the prefix used in the document, so these are the prefixes the trackers actually use.
+//
+// nyaa.si:      nyaa:seeders, nyaa:infoHash, nyaa:size ("1.2 GiB")
+// ezrss/eztv:   torrent:infoHash, torrent:contentLength, torrent:magnetURI, torrent:seeds
+// showrss:      showrss:info_hash (older feeds), tv:info_hash (current feeds)
+
+fn ext_value<'a>(extensions: &'a ExtensionMap, prefix: &str, name: &str) -> Option<&'a str> {
+    extensions.get(prefix)
+        .and_then(|e| e.get(name))
+        .and_then(|values| values.first())
+        .and_then(|ext| ext.value())
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// Fill in the item fields provided by tracker-specific namespaces. Values that are already set,
+/// e.g. from torznab attributes, are kept.
+pub fn apply_extensions(item: &mut Item, extensions: &ExtensionMap) {
+    let seeders = ext_value(extensions, "nyaa", "seeders")
+        .or_else(|| ext_value(extensions, "torrent", "seeds"));
+    if item.seeders.is_none() {
+        item.seeders = seeders.and_then(|s| s.parse().ok());
+    }
+
+    let size = ext_value(extensions, "nyaa", "size").and_then(filter::parse_size)
+        .or_else(|| ext_value(extensions, "torrent", "contentLength").and_then(|s| s.parse().ok()));
+    if item.size.is_none() {
+        item.size = size;
+    }
+
+    let info_hash = ext_value(extensions, "nyaa", "infoHash")
+        .or_else(|| ext_value(extensions, "torrent", "infoHash"))
+        .or_else(|| ext_value(extensions, "showrss", "info_hash"))
+        .or_else(|| ext_value(extensions, "tv", "info_hash"));
+    if item.info_hash.is_none() {
+        item.info_hash = info_hash.and_then(normalize_info_hash);
+    }
+
+    if item.magnet.is_none() {
+        item.magnet = ext_value(extensions, "torrent", "magnetURI").map(|m| m.to_string());
+    }
+}
+
+/// Get the info hash out of a magnet link, e.g. `magnet:?xt=urn:btih:<hash>&dn=...`.
+pub fn magnet_info_hash(url: &str) -> Option<String> {
+    if !url.starts_with("magnet:") {
+        return None;
+    }
+
+    let query = url.splitn(2, '?').nth(1)?;
+    query.split('&')
+        .filter_map(|param| {
+            let mut kv = param.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some("xt"), Some(value)) => Some(value),
+                _ => None,
+            }
+        })
+        .filter(|xt| xt.to_lowercase().starts_with("urn:btih:"))
+        .filter_map(|xt| normalize_info_hash(&xt["urn:btih:".len()..]))
+        .next()
+}
+
+/// Info hashes are either 40 hex digits or 32 base32 characters. Convert both to lowercase hex so
+/// they can be compared.
+pub fn normalize_info_hash(hash: &str) -> Option<String> {
+    let hash = hash.trim();
+
+    if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(hash.to_lowercase());
+    }
+
+    if hash.len() == 32 {
+        return base32_to_hex(hash);
+    }
+
+    debug!("Ignoring invalid info hash {}", hash);
+    None
+}
+
+fn base32_to_hex(data: &str) -> Option<String> {
+    let mut bits: u64 = 0;
+    let mut n_bits = 0;
+    let mut hex = String::with_capacity(40);
+
+    for c in data.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+
+        // only the low bits that haven't been written out yet matter
+        bits = ((bits << 5) | value) & 0x1ff;
+        n_bits += 5;
+
+        while n_bits >= 4 {
+            n_bits -= 4;
+            hex.push_str(&format!("{:x}", (bits >> n_bits) & 0xf));
+        }
+    }
+
+    Some(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rss::Channel;
+
+    const HASH: &str = "5d2e1b7e6c4a3f2b1a0987654321fedcba987654";
+    const HASH_BASE32: &str = "LUXBW7TMJI7SWGQJQ5SUGIP63S5JQ5SU";
+
+    fn fixture_items(xml: &str) -> Vec<Item> {
+        let channel = Channel::read_from(xml.as_bytes()).unwrap();
+        channel.items().iter().map(Item::from_rss).collect()
+    }
+
+    #[test]
+    fn reads_nyaa_items() {
+        let items = fixture_items(include_str!("../test/nyaa.xml"));
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].seeders, Some(128));
+        assert_eq!(items[0].size, Some(1288490188));
+        assert_eq!(items[0].info_hash.as_ref().unwrap(), HASH);
+        assert_eq!(items[0].magnet, None);
+        assert_eq!(items[0].download_url(), Some("https://nyaa.si/download/1000001.torrent"));
+
+        assert_eq!(items[1].seeders, Some(0));
+        assert_eq!(items[1].size, Some(367525888));
+        assert_eq!(items[1].info_hash.as_ref().unwrap(), "a1b2c3d4e5f60718293a4b5c6d7e8f9012345678");
+    }
+
+    #[test]
+    fn reads_ezrss_items() {
+        let items = fixture_items(include_str!("../test/ezrss.xml"));
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].seeders, Some(57));
+        assert_eq!(items[0].size, Some(734003200));
+        assert_eq!(items[0].info_hash.as_ref().unwrap(), "fedcba9876543210fedcba9876543210fedcba98");
+        assert!(items[0].magnet.as_ref().unwrap()
+            .starts_with("magnet:?xt=urn:btih:FEDCBA9876543210FEDCBA9876543210FEDCBA98&dn="));
+        // the torrent file is preferred over the magnet link
+        assert_eq!(items[0].download_url(),
+                   Some("https://eztv.example/torrents/some-show-s01e05.torrent"));
+
+        // no infoHash, so it comes from the base32 magnet link, and unparseable seeds are ignored
+        assert_eq!(items[1].seeders, None);
+        assert_eq!(items[1].size, None);
+        assert_eq!(items[1].info_hash.as_ref().unwrap(), HASH);
+        assert_eq!(items[1].download_url(), items[1].magnet.as_ref().map(|m| m.as_str()));
+    }
+
+    #[test]
+    fn reads_showrss_items() {
+        let items = fixture_items(include_str!("../test/showrss.xml"));
+        assert_eq!(items.len(), 2);
+
+        // current feeds use tv:info_hash, older ones showrss:info_hash
+        assert_eq!(items[0].info_hash.as_ref().unwrap(), "a1b2c3d4e5f60718293a4b5c6d7e8f9012345678");
+        assert_eq!(items[1].info_hash.as_ref().unwrap(), "fedcba9876543210fedcba9876543210fedcba98");
+
+        for item in &items {
+            assert_eq!(item.seeders, None);
+            assert_eq!(item.size, None);
+            assert_eq!(item.magnet, None);
+            assert!(item.download_url().unwrap().starts_with("magnet:?xt=urn:btih:"));
+        }
+    }
+
+    #[test]
+    fn keeps_values_that_are_already_set() {
+        let channel = Channel::read_from(include_str!("../test/nyaa.xml").as_bytes()).unwrap();
+        let mut item = Item::from_rss(&channel.items()[0]);
+        item.seeders = Some(1);
+        item.size = Some(2);
+        item.info_hash = Some("hash".to_string());
+
+        apply_extensions(&mut item, channel.items()[0].extensions());
+        assert_eq!(item.seeders, Some(1));
+        assert_eq!(item.size, Some(2));
+        assert_eq!(item.info_hash.as_ref().unwrap(), "hash");
+    }
+
+    #[test]
+    fn converts_base32_to_hex() {
+        assert_eq!(base32_to_hex(HASH_BASE32).unwrap(), HASH);
+        assert_eq!(base32_to_hex(&HASH_BASE32.to_lowercase()).unwrap(), HASH);
+        assert_eq!(base32_to_hex("LUXBW7tmji7swgqjQ5SUGIP63S5JQ5SU").unwrap(), HASH);
+        assert_eq!(base32_to_hex("").unwrap(), "");
+
+        // 0, 1, 8 and 9 aren't base32 digits, and padding isn't expected in info hashes
+        assert_eq!(base32_to_hex("LUXBW7TMJI7SWGQJQ5SUGIP63S5JQ5S0"), None);
+        assert_eq!(base32_to_hex("LUXBW7TMJI7SWGQJQ5SUGIP63S5JQ5S1"), None);
+        assert_eq!(base32_to_hex("LUXBW7TMJI7SWGQJQ5SUGIP63S5JQ5S8"), None);
+        assert_eq!(base32_to_hex("LUXBW7TMJI7SWGQJQ5SUGIP63S5JQ5S="), None);
+    }
+
+    #[test]
+    fn normalizes_info_hashes() {
+        assert_eq!(normalize_info_hash(HASH).unwrap(), HASH);
+        assert_eq!(normalize_info_hash(&HASH.to_uppercase()).unwrap(), HASH);
+        assert_eq!(normalize_info_hash("5D2E1B7E6C4A3F2B1A0987654321fedcba987654").unwrap(), HASH);
+        assert_eq!(normalize_info_hash(&format!(" {}\n", HASH)).unwrap(), HASH);
+        assert_eq!(normalize_info_hash(HASH_BASE32).unwrap(), HASH);
+        assert_eq!(normalize_info_hash(&HASH_BASE32.to_lowercase()).unwrap(), HASH);
+
+        // bad lengths
+        assert_eq!(normalize_info_hash(&HASH[..39]), None);
+        assert_eq!(normalize_info_hash(&format!("{}0", HASH)), None);
+        assert_eq!(normalize_info_hash(&HASH_BASE32[..31]), None);
+        assert_eq!(normalize_info_hash(&format!("{}A", HASH_BASE32)), None);
+        assert_eq!(normalize_info_hash(""), None);
+
+        // right length, wrong digits
+        assert_eq!(normalize_info_hash("5d2e1b7e6c4a3f2b1a0987654321fedcba98765g"), None);
+        assert_eq!(normalize_info_hash("0123456789abcdef0123456789abcdef"), None);
+    }
+
+    #[test]
+    fn finds_info_hashes_in_magnet_links() {
+        assert_eq!(magnet_info_hash(&format!("magnet:?xt=urn:btih:{}&dn=x", HASH)).unwrap(), HASH);
+        assert_eq!(magnet_info_hash(&format!("magnet:?dn=x&xt=URN:BTIH:{}", HASH_BASE32)).unwrap(),
+                   HASH);
+        assert_eq!(magnet_info_hash(&format!("magnet:?xt=urn:sha1:{}", HASH)), None);
+        assert_eq!(magnet_info_hash(&format!("https://t.example/?xt=urn:btih:{}", HASH)), None);
+        assert_eq!(magnet_info_hash("magnet:"), None);
+    }
+}
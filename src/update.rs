@@ -1,5 +1,539 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+
+use chrono::{DateTime, FixedOffset, Local};
+
+use reqwest::{self, Url};
+
+use commands::RTUpdate;
 use config::RTConfig;
+use feed_parse;
+use feed_util::{Feed, Feeds};
+use history_util::HistoryEntry;
+use item::Item;
+use output::{self, OutputFormat};
+use redact::{self, RedactedUrl};
+use store::Store;
+
+/// Placeholder in `torrent_add_args` that is replaced with the path of the downloaded torrent file
+/// (or the magnet link).
+const TORRENT_PATH_ARG: &str = "_TORRENT_PATH";
+
+type FetchResults = HashMap<Url, Result<Vec<Item>, String>>;
+
+const PLANNED_HEADERS: &[&str] = &["feed", "title", "handler", "argv"];
+
+/// What dispatching an item would run, for `update --dry-run`. The arguments are redacted.
+#[derive(Serialize)]
+struct PlannedDispatch {
+    feed: String,
+    title: String,
+    handler: String,
+    argv: Vec<String>,
+}
+
+/// Items dispatched in previous runs and in this one, so that the same torrent isn't added twice
+/// when it shows up in several feeds or is reposted. Guids are only unique within a feed, so they
+/// are kept together with the feed url. Items without a date are always new, so for them this is
+/// the only thing that keeps them from being added on every run.
+struct Seen {
+    info_hashes: HashSet<String>,
+    guids: HashSet<(String, String)>,
+    new_entries: Vec<HistoryEntry>,
+}
+
+impl Seen {
+    fn new() -> Seen {
+        Seen {
+            info_hashes: HashSet::new(),
+            guids: HashSet::new(),
+            new_entries: Vec::new(),
+        }
+    }
+
+    fn extend(&mut self, history: Vec<HistoryEntry>) {
+        for entry in history {
+            if let Some(hash) = entry.info_hash {
+                self.info_hashes.insert(hash);
+            }
+            if let Some(guid) = entry.guid {
+                self.guids.insert((entry.feed_url, guid));
+            }
+        }
+    }
+
+    fn contains(&self, feed: &Feed, item: &Item) -> bool {
+        item.info_hash.as_ref().map_or(false, |h| self.info_hashes.contains(h))
+            || item.id.as_ref().map_or(false, |g| {
+                self.guids.contains(&(feed.url.to_string(), g.clone()))
+            })
+    }
+
+    fn insert(&mut self, feed: &Feed, item: &Item) {
+        if let Some(ref hash) = item.info_hash {
+            self.info_hashes.insert(hash.clone());
+        }
+        if let Some(ref guid) = item.id {
+            self.guids.insert((feed.url.to_string(), guid.clone()));
+        }
+
+        let now = Local::now();
+        self.new_entries.push(HistoryEntry {
+            feed_url: feed.url.to_string(),
+            title: item.title.clone(),
+            guid: item.id.clone(),
+            info_hash: item.info_hash.clone(),
+            dispatched: now.with_timezone(now.offset()),
+        });
+    }
+}
+
+pub fn run_update(cmd: &RTUpdate, config: &RTConfig, store: &mut Store) {
+    let result = store.list_feeds();
+
+    let feeds: Feeds;
+    match result {
+        Ok(read_feeds) => feeds = read_feeds,
+        Err(err) => {
+            error!("Could not read feed db: {}. Not updating.", err);
+            return;
+        },
+    }
+
+    let result = store.list_history();
+
+    let mut seen = Seen::new();
+    match result {
+        Ok(history) => seen.extend(history),
+        Err(err) => {
+            error!("Could not read history db: {}. Not updating.", err);
+            return;
+        },
+    }
+
+    // paused feeds keep their last update, so they pick up where they left off when resumed
+    let paused = feeds.iter().filter(|f| f.paused).count();
+    let feeds: Feeds = feeds.into_iter().filter(|f| !f.paused).collect();
+
+    if paused > 0 {
+        info!("Updating {} feeds, skipping {} paused feeds.", feeds.len(), paused);
+    }
+    else {
+        info!("Updating {} feeds.", feeds.len());
+    }
+
+    let fetched = fetch_all(&feeds);
+    let now = Local::now();
+    let now = now.with_timezone(now.offset());
+
+    let mut planned = Vec::new();
+    let mut updated_feeds = Vec::new();
+    for feed in feeds {
+        let mut updated = feed.clone();
+        match fetched.get(&feed.url) {
+            Some(&Ok(ref items)) if cmd.dry_run => {
+                update_feed(&mut updated, items, &mut seen, &mut |feed, item| {
+                    planned.push(plan_dispatch(feed, item, config)?);
+                    Ok(())
+                });
+            },
+            Some(&Ok(ref items)) => {
+                record_fetch(&mut updated, &Ok(()), now);
+                update_feed(&mut updated, items, &mut seen, &mut |_, item| dispatch(item, config));
+            },
+            Some(&Err(ref err)) => {
+                record_fetch(&mut updated, &Err(err.clone()), now);
+                error!("Could not fetch feed {}: {}", RedactedUrl(&feed.url), err);
+            },
+            None => error!("Feed {} was not fetched.", RedactedUrl(&feed.url)),
+        }
+
+        if updated.last_update != feed.last_update || updated.failures != feed.failures
+            || updated.last_error != feed.last_error || updated.last_match != feed.last_match
+            || updated.last_fetch != feed.last_fetch {
+            updated_feeds.push((feed, updated));
+        }
+    }
+
+    if cmd.dry_run {
+        if let Err(err) = print_planned(&planned, cmd.format) {
+            error!("Could not print the items that would be added: {}", err);
+        }
+        return;
+    }
+
+    let record_run = counts_as_run(&fetched);
+    if fetched.is_empty() {
+        info!("There were no feeds to fetch, not recording this as a successful update.");
+    }
+    else if !record_run {
+        warn!("None of the feeds could be fetched, not recording this as a successful update.");
+    }
+
+    let result = store.transaction(&mut |tx| {
+        tx.record_history(&seen.new_entries)?;
+        for &(ref old, ref new) in &updated_feeds {
+            tx.update_feed(old, new.clone())?;
+        }
+
+        if record_run {
+            let finished = Local::now();
+            tx.record_run(finished.with_timezone(finished.offset()))?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => info!("Sucessfully updated feed db."),
+        Err(err) => error!("Could not update feed db: {}", err),
+    }
+}
+
+/// Keep track of the outcome of fetching `feed`, so `status` can report it.
+fn record_fetch(feed: &mut Feed, result: &Result<(), String>, now: DateTime<FixedOffset>) {
+    match *result {
+        Ok(()) => {
+            feed.failures = 0;
+            feed.last_error = None;
+            feed.last_fetch = Some(now);
+        },
+        Err(ref err) => {
+            feed.failures += 1;
+            feed.last_error = Some(err.clone());
+        },
+    }
+}
+
+/// A run in which every fetch failed, e.g. because the network is down, doesn't count as a
+/// successful update, and neither does one that had nothing to fetch.
+fn counts_as_run(fetched: &FetchResults) -> bool {
+    fetched.values().any(|r| r.is_ok())
+}
+
+fn print_planned(planned: &[PlannedDispatch], format: OutputFormat) -> Result<(), Box<Error>> {
+    let rows: Vec<Vec<String>> = planned.iter()
+        .map(|p| vec![p.feed.clone(), p.title.clone(), p.handler.clone(), p.argv.join(" ")])
+        .collect();
+
+    match format {
+        OutputFormat::Table => {
+            output::print_table(PLANNED_HEADERS, &rows);
+            println!("Would add {} items.", planned.len());
+            Ok(())
+        },
+        OutputFormat::Csv => output::print_csv(PLANNED_HEADERS, &rows),
+        OutputFormat::Json => output::print_json(&planned),
+    }
+}
+
+/// Fetch every distinct url once, concurrently.
+fn fetch_all(feeds: &Feeds) -> FetchResults {
+    let mut urls: Vec<Url> = feeds.iter().map(|f| f.url.clone()).collect();
+    urls.sort();
+    urls.dedup();
+
+    let handles: Vec<_> = urls.into_iter().map(|url| {
+        thread::spawn(move || {
+            // Box<Error> isn't Send, so just keep the message
+            let result = feed_parse::fetch_feed(&url).map_err(|e| e.to_string());
+            (url, result)
+        })
+    }).collect();
+
+    let mut results = FetchResults::new();
+    for handle in handles {
+        match handle.join() {
+            Ok((url, result)) => { results.insert(url, result); },
+            Err(_) => error!("Feed fetching thread panicked."),
+        }
+    }
+
+    results
+}
+
+/// Hand every new matching item to `add`, which dispatches it (or only plans to, for a dry run),
+/// and move the feed's `last_update` to the newest item seen. If adding an item fails,
+/// `last_update` stays below its date so it is tried again on the next run; the items after it
+/// that were added are then skipped since they are in `seen`.
+fn update_feed(feed: &mut Feed, items: &[Item], seen: &mut Seen,
+               add: &mut FnMut(&Feed, &Item) -> Result<(), Box<Error>>) {
+    let mut dates = Vec::new();
+    let mut oldest_failed: Option<DateTime<FixedOffset>> = None;
+
+    for item in items {
+        if !feed.is_new(item) {
+            continue;
+        }
+
+        dates.extend(item.date());
+
+        if !feed.matches(item) {
+            trace!("Item \"{}\" does not match filters.", item.title);
+            continue;
+        }
+
+        let now = Local::now();
+        feed.last_match = Some(now.with_timezone(now.offset()));
+
+        // check the history before downloading anything
+        if seen.contains(feed, item) {
+            info!("Skipping \"{}\", it was already added.", item.title);
+            continue;
+        }
+
+        info!("Adding \"{}\" from feed {}", item.title, RedactedUrl(&feed.url));
+        match add(feed, item) {
+            Ok(()) => seen.insert(feed, item),
+            Err(err) => {
+                error!("Could not add \"{}\": {}", item.title, err);
+                if let Some(date) = item.date() {
+                    if oldest_failed.map_or(true, |f| date < f) {
+                        oldest_failed = Some(date);
+                    }
+                }
+            },
+        }
+    }
+
+    let newest = dates.into_iter()
+        .filter(|date| oldest_failed.map_or(true, |f| *date < f))
+        .max();
+    if newest > feed.last_update {
+        feed.last_update = newest;
+    }
+}
+
+/// What replaces `TORRENT_PATH_ARG`: the magnet link, or the path of the torrent file, which is
+/// only downloaded if `download` is set.
+fn torrent_arg(item: &Item, config: &RTConfig, download: bool) -> Result<String, Box<Error>> {
+    let url = match item.download_url() {
+        Some(u) => u,
+        None => return Err(Box::new(io::Error::new(ErrorKind::NotFound, "Item has no link"))),
+    };
+
+    if url.starts_with("magnet:") {
+        Ok(url.to_string())
+    }
+    else if download {
+        Ok(download_torrent(url, item, config)?.to_string_lossy().into_owned())
+    }
+    else {
+        Ok(torrent_path(item, config).to_string_lossy().into_owned())
+    }
+}
+
+fn handler_args(torrent_arg: &str, config: &RTConfig) -> Vec<String> {
+    config.torrent_add_args.iter()
+        .map(|arg| arg.replace(TORRENT_PATH_ARG, torrent_arg))
+        .collect()
+}
+
+fn dispatch(item: &Item, config: &RTConfig) -> Result<(), Box<Error>> {
+    let args = handler_args(&torrent_arg(item, config, true)?, config);
+
+    debug!("Running {} {}", config.torrent_add_command, args.join(" "));
+    let status = Command::new(&config.torrent_add_command).args(&args).status()?;
+
+    if !status.success() {
+        return Err(Box::new(io::Error::new(ErrorKind::Other,
+                                           format!("{} exited with {}", config.torrent_add_command, status))));
+    }
+
+    Ok(())
+}
+
+/// Everything `dispatch` would do, without downloading or running anything.
+fn plan_dispatch(feed: &Feed, item: &Item, config: &RTConfig) -> Result<PlannedDispatch, Box<Error>> {
+    let args = handler_args(&torrent_arg(item, config, false)?, config);
+
+    Ok(PlannedDispatch {
+        feed: feed.id.clone(),
+        title: item.title.clone(),
+        handler: config.torrent_add_command.clone(),
+        argv: args.iter().map(|arg| redact::redact_str(arg)).collect(),
+    })
+}
+
+fn torrent_path(item: &Item, config: &RTConfig) -> PathBuf {
+    let mut path = config.torrent_file_cache_dir.clone().unwrap_or_else(env::temp_dir);
+    path.push(torrent_filename(item));
+    path
+}
+
+fn download_torrent(url: &str, item: &Item, config: &RTConfig) -> Result<PathBuf, Box<Error>> {
+    let path = torrent_path(item, config);
+
+    trace!("Downloading torrent to {}", path.to_string_lossy());
+
+    let mut response = reqwest::get(url)?;
+    if !response.status().is_success() {
+        return Err(Box::new(io::Error::new(ErrorKind::Other,
+                                           format!("Server returned {}", response.status()))));
+    }
+
+    let mut file = File::create(&path)?;
+    io::copy(&mut response, &mut file)?;
+
+    Ok(path)
+}
+
+fn torrent_filename(item: &Item) -> String {
+    let name: String = item.title.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+
+    format!("{}.torrent", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use feed_util::Feeds;
+
+    fn date(d: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(d).unwrap()
+    }
+
+    fn item(guid: &str, published: Option<&str>) -> Item {
+        Item {
+            title: guid.to_string(),
+            links: vec![format!("https://t.example/{}.torrent", guid)],
+            enclosures: Vec::new(),
+            id: Some(guid.to_string()),
+            published: published.map(date),
+            updated: None,
+            categories: Vec::new(),
+            size: None,
+            seeders: None,
+            info_hash: None,
+            magnet: None,
+            imdb_id: None,
+        }
+    }
+
+    fn feed(last_update: Option<&str>) -> Feed {
+        let url = Url::parse("https://t.example/rss").unwrap();
+        let mut feed = Feed::new(url, Vec::new(), &Feeds::new());
+        feed.last_update = last_update.map(date);
+        feed
+    }
+
+    /// Run `update_feed` and return the titles that were added. Adding `fail` fails.
+    fn run(feed: &mut Feed, items: &[Item], seen: &mut Seen, fail: &str) -> Vec<String> {
+        let mut added = Vec::new();
+        update_feed(feed, items, seen, &mut |_, item| {
+            if item.title == fail {
+                return Err(Box::new(io::Error::new(ErrorKind::Other, "handler failed")));
+            }
+            added.push(item.title.clone());
+            Ok(())
+        });
+        added
+    }
+
+    #[test]
+    fn failed_items_are_retried() {
+        let items = vec![
+            item("a", Some("2018-10-15T10:00:00Z")),
+            item("b", Some("2018-10-15T11:00:00Z")),
+            item("c", Some("2018-10-15T12:00:00Z")),
+        ];
+        let mut feed = feed(Some("2018-10-15T09:00:00Z"));
+        let mut seen = Seen::new();
+
+        assert_eq!(run(&mut feed, &items, &mut seen, "b"), vec!["a", "c"]);
+        assert_eq!(feed.last_update, Some(date("2018-10-15T10:00:00Z")));
+
+        assert_eq!(run(&mut feed, &items, &mut seen, ""), vec!["b"]);
+        assert_eq!(feed.last_update, Some(date("2018-10-15T12:00:00Z")));
+    }
+
+    #[test]
+    fn last_update_does_not_move_back() {
+        let items = vec![item("a", Some("2018-10-15T10:00:00Z"))];
+        let mut feed = feed(Some("2018-10-15T11:00:00Z"));
+
+        assert!(run(&mut feed, &items, &mut Seen::new(), "").is_empty());
+        assert_eq!(feed.last_update, Some(date("2018-10-15T11:00:00Z")));
+    }
+
+    #[test]
+    fn dateless_items_are_added_once() {
+        let items = vec![item("a", None)];
+        let mut feed = feed(Some("2018-10-15T11:00:00Z"));
+
+        let mut seen = Seen::new();
+        assert_eq!(run(&mut feed, &items, &mut seen, ""), vec!["a"]);
+        assert_eq!(feed.last_update, Some(date("2018-10-15T11:00:00Z")));
+
+        // a later run only knows about it from the history
+        let mut next = Seen::new();
+        next.extend(seen.new_entries);
+        assert!(run(&mut feed, &items, &mut next, "").is_empty());
+    }
+
+    #[test]
+    fn guids_are_only_compared_within_a_feed() {
+        let items = vec![item("a", None)];
+        let mut seen = Seen::new();
+        seen.insert(&feed(None), &items[0]);
+
+        let mut other = feed(None);
+        other.url = Url::parse("https://other.example/rss").unwrap();
+        assert_eq!(run(&mut other, &items, &mut seen, ""), vec!["a"]);
+    }
+
+    #[test]
+    fn info_hashes_are_compared_across_feeds() {
+        let mut first = item("a", None);
+        first.info_hash = Some("0123456789abcdef0123456789abcdef01234567".to_string());
+        let mut repost = item("b", None);
+        repost.info_hash = first.info_hash.clone();
+
+        let mut seen = Seen::new();
+        assert_eq!(run(&mut feed(None), &[first], &mut seen, ""), vec!["a"]);
+
+        let mut other = feed(None);
+        other.url = Url::parse("https://other.example/rss").unwrap();
+        assert!(run(&mut other, &[repost], &mut seen, "").is_empty());
+    }
+
+    #[test]
+    fn a_successful_fetch_clears_the_last_error() {
+        let mut feed = feed(None);
+        let now = date("2018-10-15T12:00:00Z");
+
+        record_fetch(&mut feed, &Err("timed out".to_string()), now);
+        record_fetch(&mut feed, &Err("timed out".to_string()), now);
+        assert_eq!(feed.failures, 2);
+        assert_eq!(feed.last_error, Some("timed out".to_string()));
+        assert_eq!(feed.last_fetch, None);
+
+        record_fetch(&mut feed, &Ok(()), now);
+        assert_eq!(feed.failures, 0);
+        assert_eq!(feed.last_error, None);
+        assert_eq!(feed.last_fetch, Some(now));
+    }
+
+    #[test]
+    fn runs_need_a_successful_fetch() {
+        let url = Url::parse("https://t.example/rss").unwrap();
+        let other = Url::parse("https://other.example/rss").unwrap();
+
+        let mut fetched = FetchResults::new();
+        assert!(!counts_as_run(&fetched));
+
+        fetched.insert(url, Err("timed out".to_string()));
+        assert!(!counts_as_run(&fetched));
 
-pub fn run_update(config: &RTConfig) {
-    unimplemented!();
+        fetched.insert(other, Ok(Vec::new()));
+        assert!(counts_as_run(&fetched));
+    }
 }